@@ -1,9 +1,12 @@
+use std::fmt;
+
 // Input has
 // Button A: X+int, Y+int
 // Button B: X+int, Y+int
 // Prize: X=int, Y=int
+// Signs, spacing, letter case and line endings may vary.
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Button {
     x: i64,
     y: i64,
@@ -15,61 +18,125 @@ struct Prize {
     y: i64,
 }
 
-fn parse_input(input: &str) -> (Button, Button, Prize) {
-    let lines: Vec<&str> = input.lines().collect();
+// A button with the tokens a press costs and how often it may be pressed
+#[derive(Debug, Clone)]
+struct PricedButton {
+    button: Button,
+    cost: i64,
+    max_presses: Option<i64>,
+}
 
-    // Parse A Button
-    let a_parts: Vec<&str> = lines[0].split(": ").nth(1).unwrap().split(", ").collect();
-    let a_x: i64 = a_parts[0].trim_start_matches("X+").parse::<i64>().unwrap();
-    let a_y: i64 = a_parts[1].trim_start_matches("Y+").parse::<i64>().unwrap();
+// Any number of buttons, A and B in the puzzle, and the prize they aim for
+#[derive(Debug)]
+struct Machine {
+    buttons: Vec<Button>,
+    prize: Prize,
+}
 
-    // Parse B Button
-    let b_parts: Vec<&str> = lines[1].split(": ").nth(1).unwrap().split(", ").collect();
-    let b_x: i64 = b_parts[0].trim_start_matches("X+").parse::<i64>().unwrap();
-    let b_y: i64 = b_parts[1].trim_start_matches("Y+").parse::<i64>().unwrap();
+// Where parsing failed, with machines and lines counted from 1
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    machine: usize,
+    line: usize,
+    message: String,
+}
 
-    // Parse Prize
-    let prize_parts: Vec<&str> = lines[2].split(": ").nth(1).unwrap().split(", ").collect();
-    let p_x: i64 = prize_parts[0]
-        .trim_start_matches("X=")
-        .parse::<i64>()
-        .unwrap();
-    let p_y: i64 = prize_parts[1]
-        .trim_start_matches("Y=")
-        .parse::<i64>()
-        .unwrap();
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "machine {}, line {}: {}",
+            self.machine, self.line, self.message
+        )
+    }
+}
 
-    (
-        Button { x: a_x, y: a_y },
-        Button { x: b_x, y: b_y },
-        Prize { x: p_x, y: p_y },
-    )
+// Reads one axis like "X+94", "x - 3" or "X = 8400"
+fn parse_axis(text: &str, axis: char) -> Result<i64, String> {
+    let text = text.trim();
+    let value = text
+        .strip_prefix(axis)
+        .or_else(|| text.strip_prefix(axis.to_ascii_lowercase()))
+        .ok_or_else(|| format!("expected {} in {:?}", axis, text))?
+        .trim_start();
+    let value = value.strip_prefix('=').unwrap_or(value);
+
+    // Drop spaces between the sign and the digits, parse handles the sign
+    let number: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    number
+        .parse()
+        .map_err(|_| format!("{} should be a whole number, got {:?}", axis, value.trim()))
 }
 
-fn parse_machines(content: &str) -> Vec<(Button, Button, Prize)> {
+fn parse_coordinates(text: &str) -> Result<(i64, i64), String> {
+    match text.split(',').collect::<Vec<&str>>()[..] {
+        [x, y] => Ok((parse_axis(x, 'X')?, parse_axis(y, 'Y')?)),
+        _ => Err(format!(
+            "expected X and Y separated by a comma, got {:?}",
+            text.trim()
+        )),
+    }
+}
+
+// Machines are button lines followed by their prize. Each line says what it
+// is, so blank lines between or inside machines are skipped.
+fn parse_machines(content: &str) -> Result<Vec<Machine>, ParseError> {
     let mut machines = Vec::new();
-    let mut current_lines = Vec::new();
+    let mut buttons = Vec::new();
+    let mut last_line = 0;
 
-    for line in content.lines() {
+    for (index, line) in content.lines().enumerate() {
         let line = line.trim();
+        let machine = machines.len() + 1;
+        let error = |line, message| ParseError {
+            machine,
+            line,
+            message,
+        };
+
         if line.is_empty() {
-            if !current_lines.is_empty() {
-                let machine_input = current_lines.join("\n");
-                machines.push(parse_input(&machine_input));
-                current_lines.clear();
+            continue;
+        }
+        last_line = index + 1;
+
+        let (label, coordinates) = line
+            .split_once(':')
+            .ok_or_else(|| error(last_line, format!("expected a label and ':' in {:?}", line)))?;
+        let (x, y) = parse_coordinates(coordinates).map_err(|message| error(last_line, message))?;
+
+        // Parse the buttons, A, B and any more, then the prize
+        let label = label.trim().to_ascii_lowercase();
+        if label.starts_with("button") {
+            buttons.push(Button { x, y });
+        } else if label == "prize" {
+            if buttons.is_empty() {
+                return Err(error(
+                    last_line,
+                    "Prize line without any buttons".to_string(),
+                ));
             }
+            machines.push(Machine {
+                buttons: std::mem::take(&mut buttons),
+                prize: Prize { x, y },
+            });
         } else {
-            current_lines.push(line);
+            return Err(error(
+                last_line,
+                format!("expected a Button or Prize line, got {:?}", line),
+            ));
         }
     }
 
-    // Don't forget the last machine if file doesn't end with empty line
-    if !current_lines.is_empty() {
-        let machine_input = current_lines.join("\n");
-        machines.push(parse_input(&machine_input));
+    // Don't forget the last machine if file doesn't end with its prize
+    if !buttons.is_empty() {
+        return Err(ParseError {
+            machine: machines.len() + 1,
+            line: last_line,
+            message: "expected a Prize line after the buttons".to_string(),
+        });
     }
 
-    machines
+    Ok(machines)
 }
 
 // Part 2: Implement Cramer's Rule
@@ -82,117 +149,446 @@ fn solve_with_cramers_rule(
     // button_a.x * A + button_b.x * B = prize.x
     // button_a.y * A + button_b.y * B = prize.y
 
+    // Work in i128 so the products stay exact with the part 2 offset.
+    let (a_x, a_y) = (button_a.x as i128, button_a.y as i128);
+    let (b_x, b_y) = (button_b.x as i128, button_b.y as i128);
+    let (p_x, p_y) = (prize.x as i128, prize.y as i128);
+
     // Calculate the determinant of the coefficient matrix
-    let det = (button_a.x * button_b.y) - (button_a.y * button_b.x);
+    let det = (a_x * b_y) - (a_y * b_x);
 
     if det == 0 {
         return None; // No unique solution exists
     }
 
     // Calculate determinants for A and B
-    let det_a = (prize.x * button_b.y) - (prize.y * button_b.x);
-    let det_b = (button_a.x * prize.y) - (button_a.y * prize.x);
+    let det_a = (p_x * b_y) - (p_y * b_x);
+    let det_b = (a_x * p_y) - (a_y * p_x);
+
+    // Only whole presses count, so both have to divide exactly
+    if det_a % det != 0 || det_b % det != 0 {
+        return None;
+    }
 
     // Calculate A and B (number of presses needed)
-    let a = det_a as f64 / det as f64;
-    let b = det_b as f64 / det as f64;
+    let a = det_a / det;
+    let b = det_b / det;
 
-    // Check if we have positive integer solutions
-    if a >= 0.0 && b >= 0.0 && a.fract() == 0.0 && b.fract() == 0.0 {
-        return Some((a as i64, b as i64));
+    // Check if we have non-negative solutions that fit back into i64
+    if a < 0 || b < 0 {
+        return None;
     }
+    Some((i64::try_from(a).ok()?, i64::try_from(b).ok()?))
+}
 
-    None
+// Returns (g, x, y) with a * x + b * y = g, the gcd of a and b
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
 }
 
-fn main() {
-    let content = std::fs::read_to_string("input.txt").expect("Failed to read input file");
-    let machines = parse_machines(&content);
+// Rounded down and up division, for a positive divisor
+fn floor_div(a: i128, b: i128) -> i128 {
+    a.div_euclid(b)
+}
 
-    // Token costs can be easily modified here
-    let button_a_cost: i64 = 3;
-    let button_b_cost: i64 = 1;
+fn ceil_div(a: i128, b: i128) -> i128 {
+    -(-a).div_euclid(b)
+}
 
-    // Part 1
-    let mut total_tokens_p1 = 0;
-    let mut impossible_count_p1 = 0;
+// Narrows the range of k to where start + k * step stays between 0 and max.
+// Returns false when no k is left.
+fn narrow(
+    range: &mut (Option<i128>, Option<i128>),
+    start: i128,
+    step: i128,
+    max: Option<i128>,
+) -> bool {
+    let (low, high) = match step {
+        0 => return start >= 0 && max.is_none_or(|max| start <= max),
+        _ if step > 0 => (
+            Some(ceil_div(-start, step)),
+            max.map(|max| floor_div(max - start, step)),
+        ),
+        _ => (
+            max.map(|max| ceil_div(start - max, -step)),
+            Some(floor_div(start, -step)),
+        ),
+    };
+    if let Some(low) = low {
+        range.0 = Some(range.0.map_or(low, |old| old.max(low)));
+    }
+    if let Some(high) = high {
+        range.1 = Some(range.1.map_or(high, |old| old.min(high)));
+    }
+    match *range {
+        (Some(low), Some(high)) => low <= high,
+        _ => true,
+    }
+}
 
-    println!("Part 1:");
-    println!("---------");
-    for (i, (button_a, button_b, prize)) in machines.iter().enumerate() {
+// Buttons pointing the same way have no unique solution, but the prize can
+// still be on their line. Then it's a single equation a * A + b * B = p along
+// that line, which has either no integer solutions or a whole family of them:
+// A = A0 + k * b / g, B = B0 - k * a / g. The cost changes linearly with k, so
+// the cheapest pair within the press limits sits at one end of the range of k.
+fn solve_collinear(
+    button_a: &PricedButton,
+    button_b: &PricedButton,
+    prize: &Prize,
+) -> Option<(i64, i64)> {
+    let (a_x, a_y) = (button_a.button.x as i128, button_a.button.y as i128);
+    let (b_x, b_y) = (button_b.button.x as i128, button_b.button.y as i128);
+    let (p_x, p_y) = (prize.x as i128, prize.y as i128);
+
+    // The direction the buttons share, and the prize has to lie along it
+    let (d_x, d_y) = if (a_x, a_y) != (0, 0) {
+        (a_x, a_y)
+    } else if (b_x, b_y) != (0, 0) {
+        (b_x, b_y)
+    } else {
+        // Neither button moves the claw
+        return (prize.x == 0 && prize.y == 0).then_some((0, 0));
+    };
+    if d_x * p_y != d_y * p_x {
+        return None;
+    }
+
+    // Everything is on one line, so one axis decides it. Use one that moves.
+    let (a, b, p) = if d_x != 0 {
+        (a_x, b_x, p_x)
+    } else {
+        (a_y, b_y, p_y)
+    };
+
+    let (g, x, y) = extended_gcd(a, b);
+    let g_abs = g.abs();
+    if p % g_abs != 0 {
+        return None;
+    }
+    let (a0, b0) = (x * (p / g), y * (p / g));
+    let (step_a, step_b) = (b / g, a / g);
+
+    // Range of k where both press counts stay within limits, None if unbounded
+    let mut range = (None, None);
+    let max_a = button_a.max_presses.map(i128::from);
+    let max_b = button_b.max_presses.map(i128::from);
+    if !narrow(&mut range, a0, step_a, max_a) || !narrow(&mut range, b0, -step_b, max_b) {
+        return None;
+    }
+    let (low, high) = range;
+
+    // Walk k towards the cheaper end
+    let slope = button_a.cost as i128 * step_a - button_b.cost as i128 * step_b;
+    let k = match slope {
+        0 => low.or(high).unwrap_or(0),
+        _ if slope > 0 => low?,
+        _ => high?,
+    };
+    let (presses_a, presses_b) = (a0 + k * step_a, b0 - k * step_b);
+    Some((
+        i64::try_from(presses_a).ok()?,
+        i64::try_from(presses_b).ok()?,
+    ))
+}
+
+// Cheapest presses of two buttons that win the prize, if there are any
+fn solve_pair(
+    button_a: &PricedButton,
+    button_b: &PricedButton,
+    prize: &Prize,
+) -> Option<(i64, i64)> {
+    let (a, b) = (&button_a.button, &button_b.button);
+    let det = a.x as i128 * b.y as i128 - a.y as i128 * b.x as i128;
+    if det == 0 {
+        return solve_collinear(button_a, button_b, prize);
+    }
+
+    // A unique solution is the only one, so costs don't matter
+    let (presses_a, presses_b) = solve_with_cramers_rule(a, b, prize)?;
+    let within = |presses: i64, max: Option<i64>| max.is_none_or(|max| presses <= max);
+    (within(presses_a, button_a.max_presses) && within(presses_b, button_b.max_presses))
+        .then_some((presses_a, presses_b))
+}
+
+// Most presses a button can take: its own limit, or how far it gets along an
+// axis before overshooting the prize when no button moves backwards there.
+fn press_bound(buttons: &[PricedButton], index: usize, prize: &Prize) -> Option<i64> {
+    let button = &buttons[index].button;
+    let axis_bound = |axis: fn(&Button) -> i64, target: i64| {
+        let forward = buttons.iter().all(|other| axis(&other.button) >= 0);
+        (forward && axis(button) > 0).then(|| target.div_euclid(axis(button)))
+    };
+    [
+        buttons[index].max_presses,
+        axis_bound(|button| button.x, prize.x),
+        axis_bound(|button| button.y, prize.y),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+fn total_cost(buttons: &[PricedButton], presses: &[i64]) -> i64 {
+    buttons
+        .iter()
+        .zip(presses)
+        .map(|(button, presses)| button.cost * presses)
+        .sum()
+}
+
+// Most button press counts tried in total before the search gives up
+const MAX_SEARCH_STEPS: u64 = 10_000_000;
+
+// Why the search over more than two buttons stopped without an answer, as
+// opposed to finding that the machine can't be won
+#[derive(Debug, PartialEq)]
+enum SearchError {
+    // No button has a press limit or only moves forwards along some axis
+    Unbounded,
+    // Trying every press count would take more than MAX_SEARCH_STEPS
+    TooLarge,
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::Unbounded => write!(f, "no button has a limit on its presses"),
+            SearchError::TooLarge => {
+                write!(f, "more than {} press counts to try", MAX_SEARCH_STEPS)
+            }
+        }
+    }
+}
+
+// Cheapest presses for any number of buttons. Two buttons are solved directly.
+// With more, the button with the fewest possible presses is tried at every
+// count and the others solved for what is left of the prize, so that needs a
+// press limit or buttons that only move forwards, and a small enough limit.
+fn solve_buttons(buttons: &[PricedButton], prize: &Prize) -> Result<Option<Vec<i64>>, SearchError> {
+    let mut steps_left = MAX_SEARCH_STEPS;
+    search_buttons(buttons, prize, &mut steps_left)
+}
+
+fn search_buttons(
+    buttons: &[PricedButton],
+    prize: &Prize,
+    steps_left: &mut u64,
+) -> Result<Option<Vec<i64>>, SearchError> {
+    match buttons {
+        [] => Ok((prize.x == 0 && prize.y == 0).then(Vec::new)),
+        [button] => {
+            // Pair it with a button that can't be pressed
+            let still = PricedButton {
+                button: Button { x: 0, y: 0 },
+                cost: 0,
+                max_presses: Some(0),
+            };
+            Ok(solve_pair(button, &still, prize).map(|(presses, _)| vec![presses]))
+        }
+        [button_a, button_b] => Ok(solve_pair(button_a, button_b, prize).map(|(a, b)| vec![a, b])),
+        _ => {
+            let (index, bound) = (0..buttons.len())
+                .filter_map(|index| Some((index, press_bound(buttons, index, prize)?)))
+                .min_by_key(|&(_, bound)| bound)
+                .ok_or(SearchError::Unbounded)?;
+            // A negative bound means the prize is already behind the claw
+            let counts = u64::try_from(bound).map_or(0, |bound| bound + 1);
+            *steps_left = steps_left
+                .checked_sub(counts)
+                .ok_or(SearchError::TooLarge)?;
+            let mut rest = buttons.to_vec();
+            let pressed = rest.remove(index).button;
+
+            let mut best: Option<(i64, Vec<i64>)> = None;
+            for presses in 0..=bound {
+                let remaining = Prize {
+                    x: prize.x - presses * pressed.x,
+                    y: prize.y - presses * pressed.y,
+                };
+                if let Some(mut solution) = search_buttons(&rest, &remaining, steps_left)? {
+                    solution.insert(index, presses);
+                    let cost = total_cost(buttons, &solution);
+                    if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+                        best = Some((cost, solution));
+                    }
+                }
+            }
+            Ok(best.map(|(_, solution)| solution))
+        }
+    }
+}
+
+// Cost of pressing each button: the listed cost, or the extra cost for any
+// button past the end of the list.
+fn button_costs(
+    buttons: usize,
+    costs: &[i64],
+    extra_cost: Option<i64>,
+) -> Result<Vec<i64>, String> {
+    (0..buttons)
+        .map(|i| {
+            costs.get(i).copied().or(extra_cost).ok_or_else(|| {
+                format!(
+                    "button {} has no cost, only {} are given",
+                    (b'A' + i as u8) as char,
+                    costs.len()
+                )
+            })
+        })
+        .collect()
+}
+
+// Press limit of each button, listed like the costs. None means no limit, so
+// buttons past the list just take the extra limit.
+fn button_limits(
+    buttons: usize,
+    limits: &[Option<i64>],
+    extra_limit: Option<i64>,
+) -> Vec<Option<i64>> {
+    (0..buttons)
+        .map(|i| limits.get(i).copied().unwrap_or(extra_limit))
+        .collect()
+}
+
+// Solves every machine with the prize moved by the offset, printing how each
+// one is won. Returns the total tokens, how many machines can't be won and
+// how many the search gave up on.
+fn solve_machines(
+    machines: &[Machine],
+    costs: &[i64],
+    extra_cost: Option<i64>,
+    limits: &[Option<i64>],
+    extra_limit: Option<i64>,
+    offset: i64,
+) -> Result<(i64, usize, usize), String> {
+    let mut total_tokens = 0;
+    let mut impossible_count = 0;
+    let mut gave_up_count = 0;
+
+    for (i, machine) in machines.iter().enumerate() {
         println!("Machine {}:", i + 1);
-        match solve_with_cramers_rule(button_a, button_b, prize) {
-            Some((a_presses, b_presses)) => {
-                let machine_tokens = (a_presses * button_a_cost) + (b_presses * button_b_cost);
+        let buttons: Vec<PricedButton> = button_costs(machine.buttons.len(), costs, extra_cost)
+            .map_err(|message| format!("machine {}: {}", i + 1, message))?
+            .into_iter()
+            .zip(button_limits(machine.buttons.len(), limits, extra_limit))
+            .zip(&machine.buttons)
+            .map(|((cost, max_presses), button)| PricedButton {
+                button: button.clone(),
+                cost,
+                max_presses,
+            })
+            .collect();
+        // Create modified prize with offset
+        let modified_prize = Prize {
+            x: machine.prize.x + offset,
+            y: machine.prize.y + offset,
+        };
+
+        match solve_buttons(&buttons, &modified_prize) {
+            Ok(Some(presses)) => {
+                let machine_tokens = total_cost(&buttons, &presses);
                 println!("  Solution found!");
-                println!(
-                    "  Button A presses: {} (cost: {})",
-                    a_presses,
-                    a_presses * button_a_cost
-                );
-                println!(
-                    "  Button B presses: {} (cost: {})",
-                    b_presses,
-                    b_presses * button_b_cost
-                );
+                for (i, (button, presses)) in buttons.iter().zip(&presses).enumerate() {
+                    println!(
+                        "  Button {} presses: {} (cost: {})",
+                        (b'A' + i as u8) as char,
+                        presses,
+                        presses * button.cost
+                    );
+                }
                 println!("  Total tokens needed: {}", machine_tokens);
-                total_tokens_p1 += machine_tokens;
+                total_tokens += machine_tokens;
             }
-            None => {
+            Ok(None) => {
                 println!("  No solution found");
-                impossible_count_p1 += 1;
+                impossible_count += 1;
+            }
+            Err(error) => {
+                println!("  Search gave up: {}", error);
+                gave_up_count += 1;
             }
         }
         println!();
     }
 
+    Ok((total_tokens, impossible_count, gave_up_count))
+}
+
+fn main() {
+    let content = std::fs::read_to_string("input.txt").expect("Failed to read input file");
+    let machines = match parse_machines(&content) {
+        Ok(machines) => machines,
+        Err(error) => {
+            eprintln!("Failed to parse input: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    // Token costs can be easily modified here, one per button. Buttons past
+    // the listed ones cost the extra cost, or are rejected if it's None.
+    let button_costs = [3, 1];
+    let extra_button_cost = Some(1);
+
+    // Part 1
+    // Each button may be pressed at most 100 times, listed per button like the
+    // costs, with the extra limit for any more buttons
+    let max_presses_p1 = [Some(100), Some(100)];
+    let extra_max_presses_p1 = Some(100);
+
+    println!("Part 1:");
+    println!("---------");
+    let (total_tokens_p1, impossible_count_p1, gave_up_count_p1) = match solve_machines(
+        &machines,
+        &button_costs,
+        extra_button_cost,
+        &max_presses_p1,
+        extra_max_presses_p1,
+        0,
+    ) {
+        Ok(totals) => totals,
+        Err(error) => {
+            eprintln!("Failed to solve machines: {}", error);
+            std::process::exit(1);
+        }
+    };
+
     println!("Part 1 Summary:");
     println!(
         "Total tokens needed for all possible machines: {}",
         total_tokens_p1
     );
     println!("Number of impossible machines: {}", impossible_count_p1);
+    println!(
+        "Number of machines the search gave up on: {}",
+        gave_up_count_p1
+    );
     println!();
 
     // Part 2
+    // No press limits, but the prizes are much further away
     let offset: i64 = 10000000000000;
-    let mut total_tokens_p2 = 0;
-    let mut impossible_count_p2 = 0;
 
     println!("Part 2:");
     println!("---------");
-    for (i, (button_a, button_b, prize)) in machines.iter().enumerate() {
-        println!("Machine {}:", i + 1);
-        // Create modified prize with offset
-        let modified_prize = Prize {
-            x: prize.x + offset,
-            y: prize.y + offset,
-        };
-
-        match solve_with_cramers_rule(button_a, button_b, &modified_prize) {
-            Some((a_presses, b_presses)) => {
-                let machine_tokens = (a_presses * button_a_cost) + (b_presses * button_b_cost);
-                println!("  Solution found!");
-                println!(
-                    "  Button A presses: {} (cost: {})",
-                    a_presses,
-                    a_presses * button_a_cost
-                );
-                println!(
-                    "  Button B presses: {} (cost: {})",
-                    b_presses,
-                    b_presses * button_b_cost
-                );
-                println!("  Total tokens needed: {}", machine_tokens);
-                total_tokens_p2 += machine_tokens;
-            }
-            None => {
-                println!("  No solution found");
-                impossible_count_p2 += 1;
-            }
+    let (total_tokens_p2, impossible_count_p2, gave_up_count_p2) = match solve_machines(
+        &machines,
+        &button_costs,
+        extra_button_cost,
+        &[],
+        None,
+        offset,
+    ) {
+        Ok(totals) => totals,
+        Err(error) => {
+            eprintln!("Failed to solve machines: {}", error);
+            std::process::exit(1);
         }
-        println!();
-    }
+    };
 
     println!("Part 2 Summary:");
     println!(
@@ -200,4 +596,278 @@ fn main() {
         total_tokens_p2
     );
     println!("Number of impossible machines: {}", impossible_count_p2);
+    println!(
+        "Number of machines the search gave up on: {}",
+        gave_up_count_p2
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn priced(buttons: &[(i64, i64, i64)], max_presses: Option<i64>) -> Vec<PricedButton> {
+        buttons
+            .iter()
+            .map(|&(x, y, cost)| PricedButton {
+                button: Button { x, y },
+                cost,
+                max_presses,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cramers_rule_is_exact() {
+        // The puzzle's first example machine.
+        let button_a = Button { x: 94, y: 34 };
+        let button_b = Button { x: 22, y: 67 };
+        let prize = Prize { x: 8400, y: 5400 };
+        assert_eq!(
+            solve_with_cramers_rule(&button_a, &button_b, &prize),
+            Some((80, 40))
+        );
+
+        // Off by one from a huge solution, which f64 division can round away.
+        let button_a = Button { x: 3, y: 1 };
+        let button_b = Button { x: 1, y: 2 };
+        let a = 9_007_199_254_740_993;
+        let prize = Prize { x: 3 * a, y: a + 1 };
+        assert_eq!(solve_with_cramers_rule(&button_a, &button_b, &prize), None);
+        let prize = Prize {
+            x: 3 * a + 1,
+            y: a + 2,
+        };
+        assert_eq!(
+            solve_with_cramers_rule(&button_a, &button_b, &prize),
+            Some((a, 1))
+        );
+
+        // Negative presses don't count.
+        let prize = Prize { x: 3, y: -4 };
+        assert_eq!(solve_with_cramers_rule(&button_a, &button_b, &prize), None);
+    }
+
+    #[test]
+    fn test_collinear_buttons() {
+        let solve = |a: (i64, i64), b: (i64, i64), p: (i64, i64), costs: (i64, i64)| {
+            let buttons = priced(&[(a.0, a.1, costs.0), (b.0, b.1, costs.1)], None);
+            solve_pair(&buttons[0], &buttons[1], &Prize { x: p.0, y: p.1 })
+        };
+
+        // 2A + 3B = 12 has (6, 0), (3, 2) and (0, 4), the cheapest one wins.
+        assert_eq!(solve((2, 4), (3, 6), (12, 24), (3, 1)), Some((0, 4)));
+        assert_eq!(solve((2, 4), (3, 6), (12, 24), (1, 3)), Some((6, 0)));
+        assert_eq!(solve((2, 4), (3, 6), (10, 20), (3, 1)), Some((2, 2)));
+
+        // Off the line, or not a multiple of the step along it.
+        assert_eq!(solve((2, 4), (3, 6), (12, 25), (3, 1)), None);
+        assert_eq!(solve((2, 2), (4, 4), (3, 3), (3, 1)), None);
+        assert_eq!(solve((2, 4), (3, 6), (1, 2), (3, 1)), None);
+
+        // Buttons along the y axis only, or not moving at all.
+        assert_eq!(solve((0, 5), (0, 3), (0, 13), (3, 1)), Some((2, 1)));
+        assert_eq!(solve((0, 0), (1, 1), (5, 5), (3, 1)), Some((0, 5)));
+        assert_eq!(solve((0, 0), (0, 0), (0, 0), (3, 1)), Some((0, 0)));
+
+        // Buttons pointing opposite ways still cost the fewest presses.
+        assert_eq!(solve((3, 3), (-2, -2), (1, 1), (3, 1)), Some((1, 1)));
+
+        // Big prizes along the line work too.
+        let far = 10_000_000_000_000;
+        assert_eq!(
+            solve((4, 4), (6, 6), (far, far), (3, 1)),
+            Some((1, (far - 4) / 6))
+        );
+    }
+
+    #[test]
+    fn test_press_limits() {
+        // The cheapest way needs 4 presses of B, with a limit of 3 it's (3, 2).
+        let prize = Prize { x: 12, y: 24 };
+        let buttons = priced(&[(2, 4, 3), (3, 6, 1)], Some(3));
+        assert_eq!(solve_buttons(&buttons, &prize), Ok(Some(vec![3, 2])));
+        let buttons = priced(&[(2, 4, 3), (3, 6, 1)], Some(1));
+        assert_eq!(solve_buttons(&buttons, &prize), Ok(None));
+
+        // The puzzle's first example needs 80 presses of A.
+        let prize = Prize { x: 8400, y: 5400 };
+        let buttons = priced(&[(94, 34, 3), (22, 67, 1)], Some(100));
+        assert_eq!(solve_buttons(&buttons, &prize), Ok(Some(vec![80, 40])));
+        let buttons = priced(&[(94, 34, 3), (22, 67, 1)], Some(79));
+        assert_eq!(solve_buttons(&buttons, &prize), Ok(None));
+    }
+
+    #[test]
+    fn test_more_buttons() {
+        // A third, pricier button that covers both axes at once.
+        let buttons = priced(&[(1, 0, 1), (0, 1, 1), (1, 1, 3)], None);
+        assert_eq!(
+            solve_buttons(&buttons, &Prize { x: 5, y: 7 }),
+            Ok(Some(vec![5, 7, 0]))
+        );
+        let buttons = priced(&[(1, 0, 2), (0, 1, 2), (1, 1, 3)], None);
+        assert_eq!(
+            solve_buttons(&buttons, &Prize { x: 5, y: 7 }),
+            Ok(Some(vec![0, 2, 5]))
+        );
+
+        // The same with a limit on the cheap diagonal only.
+        let mut buttons = priced(&[(1, 0, 2), (0, 1, 2), (1, 1, 3)], None);
+        buttons[2].max_presses = Some(4);
+        assert_eq!(
+            solve_buttons(&buttons, &Prize { x: 5, y: 7 }),
+            Ok(Some(vec![1, 3, 4]))
+        );
+
+        // One button, or none.
+        let buttons = priced(&[(2, 3, 1)], None);
+        assert_eq!(
+            solve_buttons(&buttons, &Prize { x: 8, y: 12 }),
+            Ok(Some(vec![4]))
+        );
+        assert_eq!(solve_buttons(&buttons, &Prize { x: 8, y: 13 }), Ok(None));
+        assert_eq!(solve_buttons(&[], &Prize { x: 0, y: 0 }), Ok(Some(vec![])));
+
+        // Machines with more than two buttons parse as well.
+        let machines = parse_machines(
+            "Button A: X+1, Y+0\nButton B: X+0, Y+1\nButton C: X+1, Y+1\nPrize: X=5, Y=7",
+        )
+        .unwrap();
+        assert_eq!(machines[0].buttons.len(), 3);
+        assert_eq!((machines[0].prize.x, machines[0].prize.y), (5, 7));
+    }
+
+    #[test]
+    fn test_search_limits() {
+        // A backwards button leaves nothing to bound the search, which is not
+        // the same as the machine being impossible.
+        let buttons = priced(&[(1, 1, 3), (-1, -1, 1), (2, 0, 1)], None);
+        let prize = Prize { x: 1, y: 1 };
+        assert_eq!(solve_buttons(&buttons, &prize), Err(SearchError::Unbounded));
+        let buttons = priced(&[(1, 1, 3), (-1, -1, 1), (2, 0, 1)], Some(5));
+        assert_eq!(solve_buttons(&buttons, &prize), Ok(Some(vec![1, 0, 0])));
+
+        // Far prizes would take too many presses to try one by one.
+        let far = 10_000_000_000_000;
+        let buttons = priced(&[(1, 0, 1), (0, 1, 1), (1, 1, 3)], None);
+        assert_eq!(
+            solve_buttons(&buttons, &Prize { x: far, y: far }),
+            Err(SearchError::TooLarge)
+        );
+        // A press limit keeps it small, it just can't get that far.
+        let buttons = priced(&[(1, 0, 1), (0, 1, 1), (1, 1, 3)], Some(100));
+        assert_eq!(solve_buttons(&buttons, &Prize { x: far, y: far }), Ok(None));
+    }
+
+    #[test]
+    fn test_button_costs() {
+        assert_eq!(button_costs(2, &[3, 1], None), Ok(vec![3, 1]));
+        assert_eq!(button_costs(4, &[3, 1], Some(2)), Ok(vec![3, 1, 2, 2]));
+        assert_eq!(
+            button_costs(3, &[3, 1], None),
+            Err("button C has no cost, only 2 are given".to_string())
+        );
+
+        // A third button gets the extra cost instead of stopping the run.
+        let machines = parse_machines(
+            "Button A: X+1, Y+0\nButton B: X+0, Y+1\nButton C: X+1, Y+1\nPrize: X=5, Y=7",
+        )
+        .unwrap();
+        assert_eq!(
+            solve_machines(&machines, &[2, 2], Some(3), &[], None, 0),
+            Ok((19, 0, 0))
+        );
+        assert_eq!(
+            solve_machines(&machines, &[2, 2], None, &[], None, 0),
+            Err("machine 1: button C has no cost, only 2 are given".to_string())
+        );
+
+        // Limits work the same way, here only the third button has one.
+        assert_eq!(
+            button_limits(3, &[Some(5)], None),
+            vec![Some(5), None, None]
+        );
+        assert_eq!(
+            solve_machines(&machines, &[2, 2], Some(3), &[None, None], Some(4), 0),
+            Ok((20, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_parse_variations() {
+        let summary = |machines: Vec<Machine>| {
+            machines
+                .iter()
+                .map(|machine| {
+                    let buttons: Vec<(i64, i64)> =
+                        machine.buttons.iter().map(|b| (b.x, b.y)).collect();
+                    (buttons, (machine.prize.x, machine.prize.y))
+                })
+                .collect::<Vec<_>>()
+        };
+        let plain = "Button A: X+94, Y+34\nButton B: X+22, Y+67\nPrize: X=8400, Y=5400\n\nButton A: X+26, Y+66\nButton B: X+67, Y+21\nPrize: X=12748, Y=12176\n";
+        let messy = "\r\n  Button A:X+94,Y+34\r\nbutton b:  x + 22 , y+67\r\nPrize: X = 8400, Y=+5400\r\n \t\r\n\r\nButton A: X+26, Y+66\r\nButton B: X+67, Y+21\r\nPRIZE: X=12748, Y=12176\r\n\r\n\r\n";
+        assert_eq!(
+            summary(parse_machines(messy).unwrap()),
+            summary(parse_machines(plain).unwrap())
+        );
+
+        // Buttons may move backwards, and prizes may sit behind the claw.
+        let machines =
+            parse_machines("Button A: X-3, Y+1\nButton B: X+1, Y-2\nPrize: X=-5, Y= - 7").unwrap();
+        assert_eq!(summary(machines), vec![(vec![(-3, 1), (1, -2)], (-5, -7))]);
+
+        // Blank lines inside a machine don't split it.
+        let machines = parse_machines(
+            "Button A: X+94, Y+34\n\nButton B: X+22, Y+67\n  \nPrize: X=8400, Y=5400",
+        )
+        .unwrap();
+        assert_eq!(
+            summary(machines),
+            vec![(vec![(94, 34), (22, 67)], (8400, 5400))]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |input: &str| parse_machines(input).unwrap_err().to_string();
+
+        let input = "Button A: X+94, Y+34\nButton B: X+22, Y+67\nPrize: X=8400, Y=5400\n\nButton A: X+26, Y+6x6\n";
+        assert_eq!(
+            error(input),
+            "machine 2, line 5: Y should be a whole number, got \"+6x6\""
+        );
+
+        let input = "Button A: X+94, Y+34\nButton B: X+22, Y+67\n\nButton A: X+26, Y+66\n";
+        assert_eq!(
+            error(input),
+            "machine 1, line 4: expected a Prize line after the buttons"
+        );
+        assert_eq!(
+            error("Button A: X+94, Y+34"),
+            "machine 1, line 1: expected a Prize line after the buttons"
+        );
+
+        assert_eq!(
+            error("Prize: X=1, Y=2"),
+            "machine 1, line 1: Prize line without any buttons"
+        );
+        assert_eq!(
+            error("Button A: X+1"),
+            "machine 1, line 1: expected X and Y separated by a comma, got \"X+1\""
+        );
+        assert_eq!(
+            error("Button A: Y+1, X+2"),
+            "machine 1, line 1: expected X in \"Y+1\""
+        );
+        assert_eq!(
+            error("Lever A: X+1, Y+2"),
+            "machine 1, line 1: expected a Button or Prize line, got \"Lever A: X+1, Y+2\""
+        );
+        assert_eq!(
+            error("Button A X+1, Y+2"),
+            "machine 1, line 1: expected a label and ':' in \"Button A X+1, Y+2\""
+        );
+    }
 }
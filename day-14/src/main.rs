@@ -1,5 +1,7 @@
-use std::collections::HashSet;
-use std::fs::read_to_string;
+use std::fs::{self, read_to_string, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
 
 #[derive(Debug)]
 struct Robot {
@@ -61,13 +63,27 @@ impl Robot {
         positions
     }
 
+    // Position `t` steps after the start, where negative t runs time
+    // backwards. Each axis wraps on its own, so only t modulo the room size
+    // matters and any t is as quick as the next.
+    fn position_at(&self, t: i64, width: i32, height: i32) -> (i32, i32) {
+        let wrap = |start: i32, velocity: i32, limit: i32| {
+            let limit = limit as i64;
+            (start as i64 + velocity as i64 * t.rem_euclid(limit)).rem_euclid(limit) as i32
+        };
+        (
+            wrap(self.initial_position.0, self.velocity.0, width),
+            wrap(self.initial_position.1, self.velocity.1, height),
+        )
+    }
+
     fn reset(&mut self) {
         self.position = self.initial_position;
     }
 }
 
 fn simulate_robots(
-    robots: &mut Vec<Robot>,
+    robots: &mut [Robot],
     steps: usize,
     width: i32,
     height: i32,
@@ -100,22 +116,16 @@ fn get_quadrant(pos: (i32, i32), width: i32, height: i32) -> Option<usize> {
     }
 }
 
-fn count_robots_in_quadrants(
-    positions: &[Vec<(i32, i32)>],
-    width: i32,
-    height: i32,
-    step: usize,
-) -> [usize; 4] {
+fn count_robots_in_quadrants(robots: &[Robot], width: i32, height: i32, steps: i64) -> [usize; 4] {
     let mut quadrant_counts = [0; 4];
     let mut middle_count = 0;
 
-    for robot_positions in positions {
-        if let Some(pos) = robot_positions.get(step) {
-            if let Some(quadrant) = get_quadrant(*pos, width, height) {
-                quadrant_counts[quadrant] += 1;
-            } else {
-                middle_count += 1;
-            }
+    for robot in robots {
+        let pos = robot.position_at(steps, width, height);
+        if let Some(quadrant) = get_quadrant(pos, width, height) {
+            quadrant_counts[quadrant] += 1;
+        } else {
+            middle_count += 1;
         }
     }
     println!("Robots on dividing lines: {}", middle_count);
@@ -123,47 +133,254 @@ fn count_robots_in_quadrants(
 }
 
 // Part 2 functions, detect christmas tree pattern
-// Use a hashmap to find step where all robots are in unique positions
-fn find_unique_positions_steps(positions: &[Vec<(i32, i32)>], max_steps: usize) -> Vec<usize> {
-    let mut unique_steps = Vec::new();
-
-    for step in 0..max_steps {
-        let mut positions_set = HashSet::new();
-        let mut all_unique = true;
-
-        // Check positions at this step
-        for robot_positions in positions {
-            if let Some(pos) = robot_positions.get(step) {
-                // If we can't insert the position, it means it's already in the set
-                if !positions_set.insert(*pos) {
-                    all_unique = false;
-                    break;
+// The tree packs most robots into a small area, so the spread of their x and
+// y positions drops far below the usual. Every robot's x repeats after `width`
+// steps and its y after `height` steps, so each axis can be searched on its
+// own and the two answers combined with the chinese remainder theorem.
+fn variance(values: impl Iterator<Item = i32>) -> f64 {
+    let (mut count, mut sum, mut sum_squares) = (0.0, 0.0, 0.0);
+    for value in values {
+        let value = value as f64;
+        count += 1.0;
+        sum += value;
+        sum_squares += value * value;
+    }
+    if count == 0.0 {
+        return 0.0;
+    }
+    let mean = sum / count;
+    sum_squares / count - mean * mean
+}
+
+fn find_tree_step(robots: &[Robot], width: i32, height: i32) -> Option<i64> {
+    // Tightest step and its variance for each axis.
+    let mut best_x = (0, f64::MAX);
+    let mut best_y = (0, f64::MAX);
+
+    for step in 0..width.max(height) {
+        if step < width {
+            let spread = variance(
+                robots
+                    .iter()
+                    .map(|robot| robot.position_at(step as i64, width, height).0),
+            );
+            if spread < best_x.1 {
+                best_x = (step, spread);
+            }
+        }
+        if step < height {
+            let spread = variance(
+                robots
+                    .iter()
+                    .map(|robot| robot.position_at(step as i64, width, height).1),
+            );
+            if spread < best_y.1 {
+                best_y = (step, spread);
+            }
+        }
+    }
+
+    crt(
+        best_x.0 as i64,
+        width as i64,
+        best_y.0 as i64,
+        height as i64,
+    )
+}
+
+// Returns (g, x, y) with a * x + b * y = g, the gcd of a and b.
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+// Smallest t >= 0 with t = a (mod m) and t = b (mod n), if there is one.
+fn crt(a: i64, m: i64, b: i64, n: i64) -> Option<i64> {
+    let (g, p, _) = extended_gcd(m, n);
+    if (b - a) % g != 0 {
+        return None;
+    }
+    // m * p = g (mod n), so stepping k times m from a covers the difference.
+    let k = ((b - a) / g * p).rem_euclid(n / g);
+    Some((a + m * k).rem_euclid(m / g * n))
+}
+
+// Where every robot is after the given number of steps.
+fn positions_at(robots: &[Robot], steps: i64, width: i32, height: i32) -> Vec<(i32, i32)> {
+    robots
+        .iter()
+        .map(|robot| robot.position_at(steps, width, height))
+        .collect()
+}
+
+// Images of the frames, so candidates can be scanned by eye
+// Black and white, true where a robot (or a label) is.
+type Image = Vec<Vec<bool>>;
+
+fn render_frame(positions: &[Vec<(i32, i32)>], step: usize, width: i32, height: i32) -> Image {
+    let mut image = vec![vec![false; width as usize]; height as usize];
+    for robot_positions in positions {
+        if let Some(&(x, y)) = robot_positions.get(step) {
+            image[y as usize][x as usize] = true;
+        }
+    }
+    image
+}
+
+// Plain PBM, where 1 is black.
+fn write_pbm(path: &Path, image: &Image) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "P1\n{} {}", image[0].len(), image.len())?;
+    for row in image {
+        let line: Vec<&str> = row.iter().map(|&on| if on { "1" } else { "0" }).collect();
+        writeln!(out, "{}", line.join(" "))?;
+    }
+    out.flush()
+}
+
+// Grayscale PNG with black robots on white.
+fn write_png(path: &Path, image: &Image) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(out, image[0].len() as u32, image.len() as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = image
+        .iter()
+        .flatten()
+        .map(|&on| if on { 0 } else { 255 })
+        .collect();
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+// 3x5 pixel font for the step numbers, row by row.
+const DIGITS: [&str; 10] = [
+    "111101101101111",
+    "010110010010111",
+    "111001111100111",
+    "111001111001111",
+    "101101111001001",
+    "111100111001111",
+    "111100111101111",
+    "111001001001001",
+    "111101111101111",
+    "111101111001111",
+];
+
+fn draw_number(image: &mut Image, number: usize, left: usize, top: usize) {
+    for (i, digit) in number.to_string().bytes().enumerate() {
+        let glyph = DIGITS[(digit - b'0') as usize].as_bytes();
+        for y in 0..5 {
+            for x in 0..3 {
+                if glyph[y * 3 + x] == b'1' {
+                    image[top + y][left + i * 4 + x] = true;
                 }
             }
         }
+    }
+}
 
-        if all_unique {
-            unique_steps.push(step);
-            if unique_steps.len() >= 5 {
-                break;
+// Tiles the frames for the given steps in rows of `columns`, each with its
+// step number above it. Tiles are widened to fit the longest number.
+fn contact_sheet(
+    positions: &[Vec<(i32, i32)>],
+    steps: Range<usize>,
+    columns: usize,
+    width: i32,
+    height: i32,
+) -> Image {
+    let (gap, label) = (4, 7);
+    let digits = steps.end.saturating_sub(1).to_string().len();
+    let tile_width = (width as usize).max(digits * 4 - 1) + gap;
+    let tile_height = label + height as usize + gap;
+    let rows = steps.len().div_ceil(columns);
+    let mut sheet = vec![vec![false; gap + columns * tile_width]; gap + rows * tile_height];
+
+    for (i, step) in steps.enumerate() {
+        let left = gap + (i % columns) * tile_width;
+        let top = gap + (i / columns) * tile_height;
+        draw_number(&mut sheet, step, left, top);
+        for (y, row) in render_frame(positions, step, width, height)
+            .into_iter()
+            .enumerate()
+        {
+            for (x, on) in row.into_iter().enumerate() {
+                sheet[top + label + y][left + x] = on;
             }
         }
     }
-    unique_steps
+    sheet
+}
+
+// Writes every frame in `steps` to `dir` as PBM and PNG, plus a contact sheet
+// with all of them.
+fn export_frames(
+    robots: &mut [Robot],
+    steps: Range<usize>,
+    width: i32,
+    height: i32,
+    dir: &Path,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let positions = simulate_robots(robots, steps.end, width, height);
+
+    for step in steps.clone() {
+        let frame = render_frame(&positions, step, width, height);
+        write_pbm(&dir.join(format!("frame-{:05}.pbm", step)), &frame)?;
+        write_png(&dir.join(format!("frame-{:05}.png", step)), &frame)?;
+    }
+    let sheet = contact_sheet(&positions, steps, 10, width, height);
+    write_png(&dir.join("contact-sheet.png"), &sheet)
+}
+
+// Robots start inside the room, so it reaches one tile past the furthest
+// start on each axis. With enough robots some start in the last row and column.
+fn detect_room_size(robots: &[Robot]) -> (i32, i32) {
+    robots.iter().fold((0, 0), |(width, height), robot| {
+        (
+            width.max(robot.position.0 + 1),
+            height.max(robot.position.1 + 1),
+        )
+    })
+}
+
+// Parses a room size like "11x7" into (width, height).
+fn parse_room_size(arg: &str) -> Option<(i32, i32)> {
+    let (width, height) = arg.split_once('x')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+fn safety_factor(quadrant_counts: &[usize; 4]) -> usize {
+    quadrant_counts.iter().product()
 }
 
 fn main() {
-    let input = read_to_string("input.txt").unwrap();
+    // Usage: day-14 [input file] [<width>x<height>] [steps] [frames dir]
+    // Without a size the room is measured from where the robots start. The
+    // frames dir gets PBM and PNG frames around the tree plus a contact sheet.
+    let args: Vec<String> = std::env::args().collect();
+    let path = args.get(1).map_or("input.txt", |arg| arg.as_str());
+    let input = read_to_string(path).unwrap();
     let mut robots: Vec<Robot> = input.lines().map(Robot::from_line).collect();
 
     // Grid dimensions
-    let width = 101;
-    let height = 103;
+    let (width, height) = match args.get(2) {
+        Some(arg) => parse_room_size(arg).expect("Room size should look like <width>x<height>"),
+        None => detect_room_size(&robots),
+    };
+    println!("Room of {}x{} with {} robots", width, height, robots.len());
 
     // Count robots in each quadrant after 100 steps aka Part 1
-    let steps = 100;
-    let all_positions = simulate_robots(&mut robots, steps, width, height);
-    let quadrant_counts = count_robots_in_quadrants(&all_positions, width, height, steps);
+    let steps: i64 = match args.get(3) {
+        Some(arg) => arg.parse().expect("Steps should be a number"),
+        None => 100,
+    };
+    let quadrant_counts = count_robots_in_quadrants(&robots, width, height, steps);
 
     // Print results
     println!("Part 1");
@@ -174,49 +391,199 @@ fn main() {
     println!("Top-right (Q2): {} robots", quadrant_counts[1]);
     println!("Bottom-left (Q3): {} robots", quadrant_counts[2]);
     println!("Bottom-right (Q4): {} robots", quadrant_counts[3]);
-    println!(
-        "Safety factor: {}",
-        quadrant_counts[0] * quadrant_counts[1] * quadrant_counts[2] * quadrant_counts[3]
-    );
+    println!("Safety factor: {}", safety_factor(&quadrant_counts));
     println!("\nPart 2");
     println!("===================================");
 
-    // Simulate for longer to find box pattern
-    let max_steps = width as usize * height as usize; // Since width and height are coprime, their LCM is their product
-    let all_positions = simulate_robots(&mut robots, max_steps, width, height);
-
-    // Find the first 5 steps where all robots have unique positions
-    let unique_steps = find_unique_positions_steps(&all_positions, max_steps);
-
-    if !unique_steps.is_empty() {
-        println!(
-            "\nFirst {} steps where all robots have unique positions:",
-            unique_steps.len()
-        );
-        for (i, step) in unique_steps.iter().enumerate() {
-            println!("\nStep #{}: {}", i + 1, step);
+    // Find the step where the robots bunch up on both axes.
+    match find_tree_step(&robots, width, height) {
+        Some(step) => {
+            println!("\nChristmas tree at step {}", step);
 
             // Visualize the positions at this step
             let mut grid = vec![vec!['.'; width as usize]; height as usize];
-            for robot_positions in &all_positions {
-                if let Some(pos) = robot_positions.get(*step) {
-                    let x = pos.0 as usize;
-                    let y = pos.1 as usize;
-                    if x < width as usize && y < height as usize {
-                        grid[y][x] = '#';
-                    }
-                }
+            for (x, y) in positions_at(&robots, step, width, height) {
+                grid[y as usize][x as usize] = '#';
             }
 
             println!("\nPattern visualization:");
             for row in grid {
                 println!("{}", row.iter().collect::<String>());
             }
+
+            if let Some(dir) = args.get(4).map(Path::new) {
+                let first = (step as usize).saturating_sub(50);
+                export_frames(&mut robots, first..first + 100, width, height, dir)
+                    .expect("Frames should be written");
+                println!(
+                    "\nWrote frames {}-{} to {}",
+                    first,
+                    first + 99,
+                    dir.display()
+                );
+            }
         }
-    } else {
-        println!(
-            "\nNo steps found where all robots have unique positions within {} steps",
-            max_steps
+        None => println!("\nNo step lines up the tightest x and y positions"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "p=0,4 v=3,-3
+p=6,3 v=-1,-3
+p=10,3 v=-1,2
+p=2,0 v=2,-1
+p=0,0 v=1,3
+p=3,0 v=-2,-2
+p=7,6 v=-1,-3
+p=3,0 v=-1,-2
+p=9,3 v=2,3
+p=7,3 v=-1,2
+p=2,4 v=2,-3
+p=9,5 v=-3,-3";
+
+    #[test]
+    fn test_example_safety_factor() {
+        let robots: Vec<Robot> = EXAMPLE.lines().map(Robot::from_line).collect();
+        let (width, height) = detect_room_size(&robots);
+        assert_eq!((width, height), (11, 7));
+        assert_eq!(parse_room_size("11x7"), Some((11, 7)));
+        assert_eq!(parse_room_size("11x0"), None);
+
+        let quadrant_counts = count_robots_in_quadrants(&robots, width, height, 100);
+        assert_eq!(quadrant_counts, [1, 3, 4, 1]);
+        assert_eq!(safety_factor(&quadrant_counts), 12);
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt(2, 3, 3, 5), Some(8));
+        assert_eq!(crt(0, 101, 0, 103), Some(0));
+        assert_eq!(crt(6911 % 101, 101, 6911 % 103, 103), Some(6911));
+        // Shared factors only work out when the remainders agree on them.
+        assert_eq!(crt(1, 4, 3, 6), Some(9));
+        assert_eq!(crt(0, 4, 1, 6), None);
+    }
+
+    #[test]
+    fn test_find_tree_step() {
+        // Robots that all meet in a 3x3 block at step 30 and scatter otherwise.
+        let (width, height, meet): (i32, i32, i32) = (11, 7, 30);
+        let robots: Vec<Robot> = (0..27)
+            .map(|i| {
+                let target = (4 + i % 3, 2 + (i / 3) % 3);
+                let velocity = (i % 11 - 5, (i * 5) % 7 - 3);
+                let start = (
+                    (target.0 - velocity.0 * meet).rem_euclid(width),
+                    (target.1 - velocity.1 * meet).rem_euclid(height),
+                );
+                Robot::from_line(&format!(
+                    "p={},{} v={},{}",
+                    start.0, start.1, velocity.0, velocity.1
+                ))
+            })
+            .collect();
+
+        assert_eq!(find_tree_step(&robots, width, height), Some(30));
+        let positions = positions_at(&robots, 30, width, height);
+        assert!(positions
+            .iter()
+            .all(|&(x, y)| (4..7).contains(&x) && (2..5).contains(&y)));
+    }
+
+    #[test]
+    fn test_export_frames() {
+        let mut robots: Vec<Robot> = EXAMPLE.lines().map(Robot::from_line).collect();
+        let dir = std::env::temp_dir().join(format!("day-14-frames-{}", std::process::id()));
+        export_frames(&mut robots, 3..15, 11, 7, &dir).unwrap();
+
+        let pbm = fs::read_to_string(dir.join("frame-00005.pbm")).unwrap();
+        let png = fs::read(dir.join("contact-sheet.png")).unwrap();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Two images per step and the contact sheet.
+        assert_eq!(files, 12 * 2 + 1);
+        assert!(png.starts_with(b"\x89PNG"));
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("11 7"));
+        let ones = pbm
+            .lines()
+            .skip(2)
+            .flat_map(|line| line.split(' '))
+            .filter(|&bit| bit == "1")
+            .count();
+        assert!(ones > 0 && ones <= 12);
+
+        // Tiles come with their step number above them.
+        let positions = simulate_robots(&mut robots, 15, 11, 7);
+        let sheet = contact_sheet(&positions, 3..15, 10, 11, 7);
+        assert_eq!((sheet[0].len(), sheet.len()), (4 + 10 * 15, 4 + 2 * 18));
+        let mut label = vec![vec![false; 3]; 5];
+        draw_number(&mut label, 7, 0, 0);
+        assert_eq!(
+            (0..5)
+                .map(|y| sheet[4 + y][4 + 4 * 15..4 + 4 * 15 + 3].to_vec())
+                .collect::<Vec<_>>(),
+            label
+        );
+        assert_eq!(
+            sheet[4 + 7..4 + 14]
+                .iter()
+                .map(|row| row[4..15].to_vec())
+                .collect::<Vec<_>>(),
+            render_frame(&positions, 3, 11, 7)
+        );
+    }
+
+    #[test]
+    fn test_position_at() {
+        let mut robots: Vec<Robot> = EXAMPLE.lines().map(Robot::from_line).collect();
+        let positions = simulate_robots(&mut robots, 50, 11, 7);
+
+        // Matches stepping one tick at a time, both ways.
+        for (robot, stepped) in robots.iter().zip(&positions) {
+            for (t, &position) in stepped.iter().enumerate() {
+                assert_eq!(robot.position_at(t as i64, 11, 7), position);
+                assert_eq!(robot.position_at(t as i64 - 77, 11, 7), position);
+            }
+        }
+
+        // The robot from the puzzle's walkthrough, far ahead and rewound.
+        let robot = Robot::from_line("p=2,4 v=2,-3");
+        assert_eq!(robot.position_at(5, 11, 7), (1, 3));
+        assert_eq!(robot.position_at(-1, 11, 7), (0, 0));
+        assert_eq!(
+            robot.position_at(1_000_000_000_000, 11, 7),
+            robot.position_at(1_000_000_000_000 % 77, 11, 7)
+        );
+        assert_eq!(
+            robot.position_at(i64::MIN, 11, 7),
+            robot.position_at(i64::MIN % 77, 11, 7)
+        );
+    }
+
+    #[test]
+    fn test_contact_sheet_fits_long_labels() {
+        // Five digit labels are wider than the 11x7 example room
+        let mut robots: Vec<Robot> = EXAMPLE.lines().map(Robot::from_line).collect();
+        let positions = simulate_robots(&mut robots, 10_003, 11, 7);
+        let sheet = contact_sheet(&positions, 9_990..10_003, 10, 11, 7);
+        let tile_width = 5 * 4 - 1 + 4;
+        assert_eq!(sheet[0].len(), 4 + 10 * tile_width);
+
+        // The last label in a row stays inside its own tile
+        let mut label = vec![vec![false; 19]; 5];
+        draw_number(&mut label, 9_999, 0, 0);
+        let left = 4 + 9 * tile_width;
+        assert_eq!(
+            (0..5)
+                .map(|y| sheet[4 + y][left..left + 19].to_vec())
+                .collect::<Vec<_>>(),
+            label
         );
     }
 }
@@ -1,4 +1,12 @@
+use crossterm::cursor::{self, MoveTo};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue};
 use itertools::Itertools;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Instruction {
@@ -20,6 +28,17 @@ impl From<char> for Instruction {
     }
 }
 
+impl From<Instruction> for char {
+    fn from(instruction: Instruction) -> Self {
+        match instruction {
+            Instruction::Up => '^',
+            Instruction::Down => 'v',
+            Instruction::Left => '<',
+            Instruction::Right => '>',
+        }
+    }
+}
+
 impl Instruction {
     fn apply(&self, x: usize, y: usize) -> (usize, usize) {
         match self {
@@ -31,53 +50,93 @@ impl Instruction {
     }
 }
 
-#[derive(Debug)]
+// Glyph for the cell at offset (dx, dy) inside a box of the given size. The
+// top row uses `O`/`[=]` so 1x1 and 2x1 boxes look like the puzzle's, lower
+// rows use `o`/`(-)` so stacked boxes can still be told apart.
+fn box_glyph(dx: usize, dy: usize, (width, _): (usize, usize)) -> char {
+    let (top, lower) = match dx {
+        _ if width == 1 => ('O', 'o'),
+        0 => ('[', '('),
+        _ if dx == width - 1 => (']', ')'),
+        _ => ('=', '-'),
+    };
+    if dy == 0 {
+        top
+    } else {
+        lower
+    }
+}
+
+fn is_box(c: char) -> bool {
+    matches!(c, 'O' | '[' | '=' | ']' | 'o' | '(' | '-' | ')')
+}
+
+// What happened when the robot tried to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    Blocked,
+    Walked,
+    Pushed(usize),
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Move::Blocked => write!(f, "blocked"),
+            Move::Walked => write!(f, "walked"),
+            Move::Pushed(1) => write!(f, "pushed 1 box"),
+            Move::Pushed(n) => write!(f, "pushed {} boxes", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Map {
     map: Vec<Vec<char>>,
     robot: (usize, usize),
+    box_size: (usize, usize),
 }
 
 impl Map {
     fn new(input: &str) -> Self {
-        // For the normal sized map in p1, we simply push values and find the
-        // robot.
-        let mut map = vec![];
-        let mut robot = (0, 0);
-        for (y, line) in input.lines().enumerate() {
-            map.push(vec![]);
-            for (x, c) in line.chars().enumerate() {
-                map[y].push(c);
-                if c == '@' {
-                    robot = (x, y);
-                }
-            }
-        }
-        Map { map, robot }
+        // For the normal sized map in p1, every cell stays a single cell.
+        Map::new_scaled(input, 1, 1)
     }
 
     fn new_wide(input: &str) -> Self {
-        // For the wide map in p2, we'll be pushing two characters per cell.
+        // For the wide map in p2, everything is twice as wide.
+        Map::new_scaled(input, 2, 1)
+    }
+
+    fn new_scaled(input: &str, width: usize, height: usize) -> Self {
+        // Every input cell becomes a width x height block. Boxes grow with it,
+        // the robot stays a single cell in the top left of its block.
         let mut map = vec![];
         let mut robot = (0, 0);
         for (y, line) in input.lines().enumerate() {
-            map.push(vec![]);
-            for (x, c) in line.chars().enumerate() {
-                if c == 'O' {
-                    map[y].push('[');
-                    map[y].push(']');
-                } else if c == '@' {
-                    // Note the x position of the robot is doubled because we
-                    // have two characters per cell.
-                    map[y].push('@');
-                    map[y].push('.');
-                    robot = (x * 2, y);
-                } else {
-                    map[y].push(c);
-                    map[y].push(c);
+            for dy in 0..height {
+                let mut row = vec![];
+                for (x, c) in line.chars().enumerate() {
+                    for dx in 0..width {
+                        row.push(match c {
+                            'O' => box_glyph(dx, dy, (width, height)),
+                            '@' if dx == 0 && dy == 0 => {
+                                robot = (x * width, y * height);
+                                '@'
+                            }
+                            '@' => '.',
+                            _ => c,
+                        });
+                    }
                 }
+                map.push(row);
             }
         }
-        Map { map, robot }
+        Map {
+            map,
+            robot,
+            box_size: (width, height),
+        }
     }
 
     fn apply_all(&mut self, instructions: &[Instruction]) {
@@ -86,7 +145,7 @@ impl Map {
         });
     }
 
-    fn apply(&mut self, instruction: &Instruction) {
+    fn apply(&mut self, instruction: &Instruction) -> Move {
         // Get the new position of the robot. and our next position.
         let (x, y) = self.robot;
         let (new_x, new_y) = instruction.apply(x, y);
@@ -94,36 +153,70 @@ impl Map {
         // Do some base case checks.
         if self.map[new_y][new_x] == '#' {
             // We reached a wall.
-            return;
+            return Move::Blocked;
         } else if self.map[new_y][new_x] == '.' {
             // we can simply move there.
             self.map[y][x] = '.';
             self.map[new_y][new_x] = '@';
             self.robot = (new_x, new_y);
-            return;
+            return Move::Walked;
         }
 
         // Try to recursively shift the boxes.
-        self.shift(new_x, new_y, instruction);
+        let pushed = self.shift(new_x, new_y, instruction);
 
         // See if the shift worked and we can now move.
         if self.map[new_y][new_x] == '.' {
             self.map[y][x] = '.';
             self.map[new_y][new_x] = '@';
             self.robot = (new_x, new_y);
+            return Move::Pushed(pushed);
         }
+        Move::Blocked
     }
 
-    fn shift(&mut self, x: usize, y: usize, instruction: &Instruction) {
+    fn shift(&mut self, x: usize, y: usize, instruction: &Instruction) -> usize {
         // We are at a box now. Let's see if we can move it and all other boxes
-        // in that can move with it.
-        if let Some(moves) = self.can_move(x, y, instruction) {
-            let moves = moves.into_iter().unique().collect::<Vec<_>>();
-            for (x, y) in moves {
-                let (new_x, new_y) = instruction.apply(x, y);
-                (self.map[y][x], self.map[new_y][new_x]) = (self.map[new_y][new_x], self.map[y][x]);
+        // in that can move with it. Returns how many boxes moved.
+        let Some(boxes) = self.can_move(x, y, instruction) else {
+            return 0;
+        };
+        let (width, height) = self.box_size;
+
+        // Lift every box off the map first so they can't overwrite each other.
+        for &(box_x, box_y) in &boxes {
+            for y in box_y..box_y + height {
+                for x in box_x..box_x + width {
+                    self.map[y][x] = '.';
+                }
+            }
+        }
+        for &(box_x, box_y) in &boxes {
+            let (new_x, new_y) = instruction.apply(box_x, box_y);
+            for dy in 0..height {
+                for dx in 0..width {
+                    self.map[new_y + dy][new_x + dx] = box_glyph(dx, dy, self.box_size);
+                }
             }
         }
+        boxes.len()
+    }
+
+    // Top left corner of the box covering (x, y).
+    fn box_at(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if !is_box(self.map[y][x]) {
+            return None;
+        }
+
+        // Go up to the top row, then left to the first column.
+        let (mut x, mut y) = (x, y);
+        while matches!(self.map[y][x], 'o' | '(' | '-' | ')') {
+            y -= 1;
+        }
+        while !matches!(self.map[y][x], 'O' | '[') {
+            x -= 1;
+        }
+        Some((x, y))
     }
 
     fn can_move(
@@ -132,52 +225,114 @@ impl Map {
         y: usize,
         instruction: &Instruction,
     ) -> Option<Vec<(usize, usize)>> {
-        // Get the position of the place I need to move.
-        let (new_x, new_y) = instruction.apply(x, y);
+        // Collect the box at (x, y) and every box it pushes, by their top left
+        // corners. Any of them hitting a wall means nothing moves.
+        let (width, height) = self.box_size;
+        let mut boxes = vec![self.box_at(x, y)?];
+        let mut i = 0;
+        while i < boxes.len() {
+            let (box_x, box_y) = boxes[i];
+            i += 1;
 
-        // Check for another part of the box (p2).
-        let other = match (self.map[y][x], instruction) {
-            ('[', Instruction::Up | Instruction::Down) => Some((x + 1, y)),
-            (']', Instruction::Up | Instruction::Down) => Some((x - 1, y)),
-            _ => None,
-        };
-        let other_new = other.map(|(x, y)| instruction.apply(x, y));
-
-        // Do some base case checks.
-        match (self.map[new_y][new_x], other, other_new) {
-            // Do we hit a wall?
-            ('#', _, _) => return None,
-            (_, _, Some((x, y))) if self.map[y][x] == '#' => return None,
-
-            // Are we free to move?
-            ('.', _, None) => return Some(vec![(x, y)]),
-            ('.', Some((other_x, other_y)), Some((other_new_x, other_new_y)))
-                if self.map[other_new_y][other_new_x] == '.' =>
-            {
-                return Some(vec![(x, y), (other_x, other_y)])
+            for y in box_y..box_y + height {
+                for x in box_x..box_x + width {
+                    let (new_x, new_y) = instruction.apply(x, y);
+                    if self.map[new_y][new_x] == '#' {
+                        return None;
+                    }
+                    if let Some(other) = self.box_at(new_x, new_y) {
+                        if other != (box_x, box_y) && !boxes.contains(&other) {
+                            boxes.push(other);
+                        }
+                    }
+                }
             }
+        }
+        Some(boxes)
+    }
 
-            _ => (),
+    // Applies the instructions like `apply_all`, but checks the warehouse after
+    // every move and stops at the first one that breaks it.
+    fn apply_checked(&mut self, instructions: &[Instruction]) -> Result<(), Violation> {
+        let start = self.clone();
+        let violation = |step, instruction, problem, map: &Map| Violation {
+            step,
+            instruction,
+            problem,
+            map: map.clone(),
+        };
+        let boxes = start
+            .check(&start)
+            .map_err(|problem| violation(0, None, problem, &start))?;
+
+        for (i, instruction) in instructions.iter().enumerate() {
+            self.apply(instruction);
+            let problem = match self.check(&start) {
+                Ok(count) if count == boxes => continue,
+                Ok(count) => format!("expected {} boxes, found {}", boxes, count),
+                Err(problem) => problem,
+            };
+            return Err(violation(i + 1, Some(*instruction), problem, self));
         }
+        Ok(())
+    }
 
-        // At this point, we know we need to move at least one box that's in the way.
-        // Let's see if we can move it (recursion bruh).
-        let mut all_moves = vec![];
-        if self.map[new_y][new_x] != '.' {
-            all_moves.extend(self.can_move(new_x, new_y, instruction)?);
+    // Checks the warehouse has the same walls as `start`, a single robot where
+    // we think it is and only whole boxes. Returns how many boxes there are.
+    fn check(&self, start: &Map) -> Result<usize, String> {
+        if self.map.len() != start.map.len()
+            || self
+                .map
+                .iter()
+                .zip(&start.map)
+                .any(|(a, b)| a.len() != b.len())
+        {
+            return Err("map changed size".to_string());
         }
-        if let Some((other_new_x, other_new_y)) = other_new {
-            if self.map[other_new_y][other_new_x] != '.' {
-                all_moves.extend(self.can_move(other_new_x, other_new_y, instruction)?);
+
+        let (width, height) = self.box_size;
+        let mut covered: Vec<Vec<bool>> =
+            self.map.iter().map(|row| vec![false; row.len()]).collect();
+        let mut robots = vec![];
+        let mut boxes = 0;
+        for (y, row) in self.map.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                if (c == '#') != (start.map[y][x] == '#') {
+                    return Err(format!("wall changed at ({}, {})", x, y));
+                }
+                match c {
+                    '@' => robots.push((x, y)),
+                    'O' | '[' if !covered[y][x] => {
+                        // A top left corner, the rest of the box has to follow.
+                        for dy in 0..height {
+                            for dx in 0..width {
+                                let (box_x, box_y) = (x + dx, y + dy);
+                                let cell = self.map.get(box_y).and_then(|row| row.get(box_x));
+                                if cell != Some(&box_glyph(dx, dy, self.box_size))
+                                    || covered[box_y][box_x]
+                                {
+                                    return Err(format!("broken box at ({}, {})", x, y));
+                                }
+                                covered[box_y][box_x] = true;
+                            }
+                        }
+                        boxes += 1;
+                    }
+                    c if is_box(c) && !covered[y][x] => {
+                        return Err(format!("stray box piece {:?} at ({}, {})", c, x, y));
+                    }
+                    _ => (),
+                }
             }
         }
 
-        // We need to include ourself as well as our partner (if we have one).
-        all_moves.push((x, y));
-        if let Some((other_x, other_y)) = other {
-            all_moves.push((other_x, other_y));
+        if robots != [self.robot] {
+            return Err(format!(
+                "expected the robot at {:?}, found {:?}",
+                self.robot, robots
+            ));
         }
-        Some(all_moves)
+        Ok(boxes)
     }
 
     fn gps(&self) -> usize {
@@ -186,8 +341,8 @@ impl Map {
             .enumerate()
             .flat_map(|(y, line)| {
                 line.iter().enumerate().map(move |(x, c)| {
-                    // For p1, we are looking for 'O', for p2 we are looking for
-                    // the left side of the box '['.
+                    // Boxes are measured from their top left corner, which is
+                    // 'O' for single width boxes and '[' for wider ones.
                     if *c == '[' || *c == 'O' {
                         y * 100 + x
                     } else {
@@ -199,16 +354,324 @@ impl Map {
     }
 }
 
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.map {
+            writeln!(f, "{}", line.iter().collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps the moves made so far with a snapshot of the map before each one, so
+// they can be undone and redone.
+struct Session {
+    map: Map,
+    history: Vec<Map>,
+    moves: Vec<Instruction>,
+    undone: Vec<Instruction>,
+}
+
+impl Session {
+    fn new(map: Map) -> Self {
+        Session {
+            map,
+            history: vec![],
+            moves: vec![],
+            undone: vec![],
+        }
+    }
+
+    fn apply(&mut self, instruction: Instruction) {
+        self.history.push(self.map.clone());
+        self.map.apply(&instruction);
+        self.moves.push(instruction);
+        self.undone.clear();
+    }
+
+    fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some(map) => {
+                self.map = map;
+                self.undone.extend(self.moves.pop());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn redo(&mut self) -> bool {
+        match self.undone.pop() {
+            Some(instruction) => {
+                // apply() clears the redo stack, so keep the rest of it around.
+                let undone = std::mem::take(&mut self.undone);
+                self.apply(instruction);
+                self.undone = undone;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // The recorded moves in the puzzle's format, wrapped into lines.
+    fn move_string(&self) -> String {
+        self.moves
+            .chunks(70)
+            .map(|chunk| chunk.iter().map(|&m| char::from(m)).collect::<String>())
+            .join("\n")
+    }
+}
+
+// Writes the map after every move to `frames.txt` in `dir`, and one line per
+// move to `index.txt` with what happened and the line its frame starts on.
+fn record_replay(mut map: Map, instructions: &[Instruction], dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut frames = BufWriter::new(File::create(dir.join("frames.txt"))?);
+    let mut index = BufWriter::new(File::create(dir.join("index.txt"))?);
+
+    // Every frame is a header, the map and a blank line.
+    let frame_lines = map.map.len() + 2;
+
+    writeln!(frames, "move 0: start, gps {}", map.gps())?;
+    write!(frames, "{}", map)?;
+    writeln!(frames)?;
+    writeln!(index, "move\tdir\tresult\trobot\tgps\tline")?;
+    writeln!(
+        index,
+        "0\t-\tstart\t{},{}\t{}\t1",
+        map.robot.0,
+        map.robot.1,
+        map.gps()
+    )?;
+
+    for (i, instruction) in instructions.iter().enumerate() {
+        let step = i + 1;
+        let result = map.apply(instruction);
+        let gps = map.gps();
+
+        writeln!(
+            frames,
+            "move {}: {} {}, gps {}",
+            step,
+            char::from(*instruction),
+            result,
+            gps
+        )?;
+        write!(frames, "{}", map)?;
+        writeln!(frames)?;
+        writeln!(
+            index,
+            "{}\t{}\t{}\t{},{}\t{}\t{}",
+            step,
+            char::from(*instruction),
+            result,
+            map.robot.0,
+            map.robot.1,
+            gps,
+            step * frame_lines + 1
+        )?;
+    }
+
+    frames.flush()?;
+    index.flush()
+}
+
+// The first move after which the warehouse was broken, and what it looked like.
+#[derive(Debug)]
+struct Violation {
+    step: usize,
+    instruction: Option<Instruction>,
+    problem: String,
+    map: Map,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.instruction {
+            Some(instruction) => write!(f, "move {} ({})", self.step, char::from(instruction))?,
+            None => write!(f, "start")?,
+        }
+        write!(f, ": {}\n{}", self.problem, self.map)
+    }
+}
+
+// Small xorshift generator, so fuzz runs can be repeated from their seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// A random warehouse in the puzzle's format: walls all around, some walls and
+// plenty of boxes inside and the robot on one of the free cells.
+fn random_warehouse(rng: &mut Rng, width: usize, height: usize) -> String {
+    let mut map = vec![vec!['#'; width]; height];
+    for row in map.iter_mut().take(height - 1).skip(1) {
+        for cell in row.iter_mut().take(width - 1).skip(1) {
+            *cell = match rng.below(10) {
+                0 => '#',
+                1..=4 => 'O',
+                _ => '.',
+            };
+        }
+    }
+    let (x, y) = (1 + rng.below(width - 2), 1 + rng.below(height - 2));
+    map[y][x] = '@';
+    map.iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
+}
+
+fn random_instructions(rng: &mut Rng, count: usize) -> Vec<Instruction> {
+    let directions = [
+        Instruction::Up,
+        Instruction::Down,
+        Instruction::Left,
+        Instruction::Right,
+    ];
+    (0..count).map(|_| directions[rng.below(4)]).collect()
+}
+
+// Runs random instructions on random warehouses at every scale, checking each
+// move. Returns the first failing warehouse and moves with what went wrong.
+fn fuzz(
+    seed: u64,
+    rounds: usize,
+    scales: &[(usize, usize)],
+) -> Option<(String, Vec<Instruction>, Violation)> {
+    let mut rng = Rng(seed.max(1));
+    for _ in 0..rounds {
+        let (width, height) = (4 + rng.below(9), 4 + rng.below(9));
+        let input = random_warehouse(&mut rng, width, height);
+        let instructions = random_instructions(&mut rng, 100);
+        for &(width, height) in scales {
+            let mut map = Map::new_scaled(&input, width, height);
+            if let Err(violation) = map.apply_checked(&instructions) {
+                return Some((input, instructions, violation));
+            }
+        }
+    }
+    None
+}
+
+fn draw(out: &mut impl Write, session: &Session, status: &str) -> io::Result<()> {
+    queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+    // Raw mode doesn't return the cursor to the start of the line for us.
+    for line in session.map.to_string().lines() {
+        write!(out, "{}\r\n", line)?;
+    }
+    write!(
+        out,
+        "moves: {}  gps: {}\r\n{}\r\n",
+        session.moves.len(),
+        session.map.gps(),
+        status
+    )?;
+    out.flush()
+}
+
+// Raw mode with a hidden cursor, put back when dropped so the terminal is
+// usable again however play ends.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let raw = RawTerminal;
+        execute!(io::stdout(), cursor::Hide)?;
+        Ok(raw)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn play(map: Map, save_path: &str) -> io::Result<()> {
+    let _raw = RawTerminal::enter()?;
+    let mut stdout = io::stdout();
+    let mut session = Session::new(map);
+    let help = "arrows: move  u: undo  r: redo  s: save  q: quit";
+    let mut status = help.to_string();
+
+    draw(&mut stdout, &session, &status)?;
+
+    loop {
+        // Windows reports key releases too, only presses count.
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        status = help.to_string();
+        match key.code {
+            KeyCode::Up => session.apply(Instruction::Up),
+            KeyCode::Down => session.apply(Instruction::Down),
+            KeyCode::Left => session.apply(Instruction::Left),
+            KeyCode::Right => session.apply(Instruction::Right),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+            KeyCode::Char('u') => {
+                status = if session.undo() {
+                    help
+                } else {
+                    "nothing to undo"
+                }
+                .to_string()
+            }
+            KeyCode::Char('r') => {
+                status = if session.redo() {
+                    help
+                } else {
+                    "nothing to redo"
+                }
+                .to_string()
+            }
+            KeyCode::Char('s') => {
+                fs::write(save_path, session.move_string() + "\n")?;
+                status = format!("saved {} moves to {}", session.moves.len(), save_path);
+            }
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            _ => (),
+        }
+        draw(&mut stdout, &session, &status)?;
+    }
+
+    Ok(())
+}
+
+// Parses a scale like "3x2" into (width, height).
+fn parse_scale(arg: &str) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let (width, height) = arg
+        .split_once('x')
+        .ok_or_else(|| format!("Scale should look like <width>x<height>, got {:?}", arg))?;
+    let (width, height) = (width.parse()?, height.parse()?);
+    if width == 0 || height == 0 {
+        return Err("Scale must be at least 1x1".into());
+    }
+    Ok((width, height))
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let input = include_str!("../input.txt");
-    let (map, instructions) = input
+    let (map_input, instructions) = input
         .split_once("\n\n")
         .or_else(|| input.split_once("\r\n\r\n"))
         .expect("Input should contain map and instructions separated by blank line");
 
     // Parse maps.
-    let mut wide_map = Map::new_wide(map);
-    let mut map = Map::new(map);
+    let mut wide_map = Map::new_wide(map_input);
+    let mut map = Map::new(map_input);
 
     // Parse instructions.
     let instructions: Vec<Instruction> = instructions
@@ -217,14 +680,287 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map(Instruction::from)
         .collect();
 
+    // `day-15 [play | replay | check | fuzz] [wide | <width>x<height>]`: play
+    // drives the robot from the keyboard, replay writes every step to replay/,
+    // check validates the warehouse after every move while solving and fuzz
+    // does the same on random warehouses. A scale also solves the scaled map.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mode = args
+        .first()
+        .map(String::as_str)
+        .filter(|arg| ["play", "replay", "check", "fuzz"].contains(arg));
+    let scale = match args.get(usize::from(mode.is_some())).map(String::as_str) {
+        Some("wide") => Some((2, 1)),
+        Some(arg) => Some(parse_scale(arg)?),
+        None => None,
+    };
+    let scaled_map = scale.map(|(width, height)| Map::new_scaled(map_input, width, height));
+
+    match mode {
+        Some("play") => {
+            play(scaled_map.unwrap_or(map), "recording.txt")?;
+            return Ok(());
+        }
+        Some("fuzz") => {
+            let (seed, rounds) = (15, 1000);
+            let mut scales = vec![(1, 1), (2, 1), (3, 2)];
+            scales.extend(scale);
+            if let Some((input, instructions, violation)) = fuzz(seed, rounds, &scales) {
+                println!("{}\n", input);
+                println!(
+                    "{}\n",
+                    instructions
+                        .iter()
+                        .map(|&m| char::from(m))
+                        .collect::<String>()
+                );
+                println!("{}", violation);
+                return Err("Fuzzing found a broken warehouse".into());
+            }
+            println!("{} random warehouses passed at scales {:?}", rounds, scales);
+            return Ok(());
+        }
+        Some("replay") => {
+            let dir = Path::new("replay");
+            record_replay(scaled_map.unwrap_or(map), &instructions, dir)?;
+            println!(
+                "Wrote {} frames to {}",
+                instructions.len() + 1,
+                dir.display()
+            );
+            return Ok(());
+        }
+        _ => (),
+    }
+
+    let check = mode == Some("check");
+    let solve = |map: &mut Map| {
+        if check {
+            map.apply_checked(&instructions).map_err(|violation| {
+                println!("{}", violation);
+                "Warehouse check failed"
+            })
+        } else {
+            map.apply_all(&instructions);
+            Ok(())
+        }
+    };
+
     // Part 1
-    map.apply_all(&instructions);
+    solve(&mut map)?;
     println!("p1: {}", map.gps());
 
     // Part 2
-    wide_map.apply_all(&instructions);
+    solve(&mut wide_map)?;
     println!("p2: {}", wide_map.gps());
 
+    if let (Some(mut scaled_map), Some((width, height))) = (scaled_map, scale) {
+        solve(&mut scaled_map)?;
+        println!("{}x{}: {}", width, height, scaled_map.gps());
+    }
+
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL_EXAMPLE: &str = "########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########";
+
+    #[test]
+    fn test_session_undo_redo() {
+        let mut session = Session::new(Map::new(SMALL_EXAMPLE));
+        let start = session.map.to_string();
+
+        for c in "<^^>>>vv<v>>v<<".chars() {
+            session.apply(Instruction::from(c));
+        }
+        assert_eq!(session.map.gps(), 2028);
+        let end = session.map.to_string();
+
+        // Undo everything, then redo it all again.
+        while session.undo() {}
+        assert_eq!(session.map.to_string(), start);
+        while session.redo() {}
+        assert_eq!(session.map.to_string(), end);
+
+        // A new move after undoing drops the redo history.
+        session.undo();
+        session.apply(Instruction::Up);
+        assert!(!session.redo());
+        assert_eq!(session.move_string(), "<^^>>>vv<v>>v<^");
+    }
+
+    #[test]
+    fn test_scaled_boxes() {
+        let input = "#######
+#...#.#
+#.....#
+#..OO@#
+#..O..#
+#.....#
+#######";
+
+        // 1x1 is the map as given, and solves the small example like the puzzle.
+        assert_eq!(
+            Map::new_scaled(input, 1, 1).to_string(),
+            input.to_string() + "\n"
+        );
+        let mut small = Map::new_scaled(SMALL_EXAMPLE, 1, 1);
+        let moves: Vec<Instruction> = "<^^>>>vv<v>>v<<".chars().map(Instruction::from).collect();
+        small.apply_all(&moves);
+        assert_eq!(
+            small.to_string(),
+            "########
+#....OO#
+##.....#
+#.....O#
+#.#O@..#
+#...O..#
+#...O..#
+########
+"
+        );
+
+        // 2x1 is the puzzle's wide map, before and after its example moves.
+        let mut wide = Map::new_scaled(input, 2, 1);
+        assert_eq!(
+            wide.to_string(),
+            "##############
+##......##..##
+##..........##
+##....[][]@.##
+##....[]....##
+##..........##
+##############
+"
+        );
+        let moves: Vec<Instruction> = "<vv<<^^<<^^".chars().map(Instruction::from).collect();
+        wide.apply_all(&moves);
+        assert_eq!(
+            wide.to_string(),
+            "##############
+##...[].##..##
+##...@.[]...##
+##....[]....##
+##..........##
+##..........##
+##############
+"
+        );
+
+        // 3x2 boxes push each other and are measured from their top left corner.
+        let mut map = Map::new_scaled(input, 3, 2);
+        let corners = |map: &Map| {
+            let mut corners = vec![];
+            for (y, row) in map.map.iter().enumerate() {
+                for (x, &c) in row.iter().enumerate() {
+                    if c == '[' {
+                        corners.push((x, y));
+                    }
+                }
+            }
+            corners
+        };
+        assert_eq!(corners(&map), vec![(9, 6), (12, 6), (9, 8)]);
+        assert_eq!(map.map[7][9..15], "(-)(-)".chars().collect::<Vec<_>>());
+
+        map.apply_all(&[Instruction::Left, Instruction::Left]);
+        assert_eq!(map.robot, (13, 6));
+        assert_eq!(corners(&map), vec![(7, 6), (10, 6), (9, 8)]);
+
+        // Pushing the lower box up lifts both boxes resting on it.
+        for c in "vvvv<<<^".chars() {
+            map.apply(&Instruction::from(c));
+        }
+        assert_eq!(map.robot, (10, 9));
+        assert_eq!(corners(&map), vec![(7, 5), (10, 5), (9, 7)]);
+        assert_eq!(map.gps(), 507 + 510 + 709);
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let map = Map::new(SMALL_EXAMPLE);
+        let instructions: Vec<Instruction> =
+            "<^^>>>vv<v>>v<<".chars().map(Instruction::from).collect();
+        let dir = std::env::temp_dir().join(format!("day-15-replay-{}", std::process::id()));
+        record_replay(map.clone(), &instructions, &dir).unwrap();
+
+        let frames = fs::read_to_string(dir.join("frames.txt")).unwrap();
+        let index = fs::read_to_string(dir.join("index.txt")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // A header line plus one line per move and the start.
+        let rows: Vec<&str> = index.lines().collect();
+        assert_eq!(rows.len(), instructions.len() + 2);
+        assert_eq!(rows[1], format!("0\t-\tstart\t2,2\t{}\t1", map.gps()));
+        assert!(rows[4].starts_with("3\t^\tblocked\t2,1\t"));
+        assert!(rows[5].starts_with("4\t>\tpushed 1 box\t3,1\t"));
+        assert!(rows[6].starts_with("5\t>\tpushed 2 boxes\t4,1\t"));
+
+        // The line in the index points at that move's frame.
+        let frame_lines: Vec<&str> = frames.lines().collect();
+        let line: usize = rows[6].rsplit('\t').next().unwrap().parse().unwrap();
+        assert_eq!(
+            frame_lines[line - 1],
+            format!("move 5: > pushed 2 boxes, gps {}", map.gps() + 3)
+        );
+        assert_eq!(frame_lines[line], "########");
+        assert!(frame_lines[frame_lines.len() - 10].ends_with(", gps 2028"));
+    }
+
+    #[test]
+    fn test_check() {
+        // Solving the puzzle example keeps every invariant.
+        let mut map = Map::new_wide(SMALL_EXAMPLE);
+        let instructions: Vec<Instruction> =
+            "<^^>>>vv<v>>v<<".chars().map(Instruction::from).collect();
+        assert!(map.apply_checked(&instructions).is_ok());
+        assert_eq!(map.check(&map), Ok(6));
+
+        // Half a box, a second robot and a moved wall are all caught.
+        let mut broken = map.clone();
+        let (x, y) = map.box_at(8, 1).unwrap();
+        broken.map[y][x + 1] = '.';
+        assert_eq!(
+            broken.check(&map),
+            Err(format!("broken box at ({}, {})", x, y))
+        );
+
+        let mut broken = map.clone();
+        broken.map[6][2] = '@';
+        assert!(broken
+            .check(&map)
+            .unwrap_err()
+            .starts_with("expected the robot"));
+
+        let mut broken = map.clone();
+        broken.map[6][2] = '#';
+        assert_eq!(
+            broken.check(&map),
+            Err("wall changed at (2, 6)".to_string())
+        );
+
+        // The violation points at the move that broke things.
+        let mut broken = Map::new(SMALL_EXAMPLE);
+        broken.robot = (1, 1);
+        let violation = broken.apply_checked(&instructions).unwrap_err();
+        assert_eq!(violation.step, 0);
+        assert!(violation
+            .to_string()
+            .starts_with("start: expected the robot"));
+    }
+
+    #[test]
+    fn test_fuzz() {
+        assert!(fuzz(15, 200, &[(1, 1), (2, 1), (3, 2), (1, 3)]).is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -11,17 +11,33 @@ enum Direction {
 }
 
 impl Direction {
-    fn turn_cost(&self, other: &Direction) -> i32 {
+    fn turn_cost(&self, other: &Direction, rules: &MoveRules) -> i32 {
         if self == other {
             0
-        } else if (self == &Direction::North && other == &Direction::South)
-            || (self == &Direction::South && other == &Direction::North)
-            || (self == &Direction::East && other == &Direction::West)
-            || (self == &Direction::West && other == &Direction::East)
-        {
-            2000 // 180 degree turn costs 2000 points (two 90-degree turns)
+        } else if *other == self.turn_left() {
+            rules.turn_left
+        } else if *other == self.turn_right() {
+            rules.turn_right
         } else {
-            1000 // 90 degree turn costs 1000 points
+            rules.reverse
+        }
+    }
+
+    fn turn_left(&self) -> Direction {
+        match self {
+            Direction::North => Direction::West,
+            Direction::West => Direction::South,
+            Direction::South => Direction::East,
+            Direction::East => Direction::North,
+        }
+    }
+
+    fn turn_right(&self) -> Direction {
+        match self {
+            Direction::North => Direction::East,
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
         }
     }
 
@@ -44,12 +60,51 @@ impl Direction {
     }
 }
 
+// Costs for moving through the maze and the direction we start out facing
+#[derive(Debug, Clone, Copy)]
+struct MoveRules {
+    step: i32,
+    turn_left: i32,
+    turn_right: i32,
+    reverse: i32,
+    start_facing: Direction,
+}
+
+impl Default for MoveRules {
+    // The reindeer's rules: 90 degree turns cost 1000, a U-turn is two of them
+    fn default() -> Self {
+        MoveRules {
+            step: 1,
+            turn_left: 1000,
+            turn_right: 1000,
+            reverse: 2000,
+            start_facing: Direction::East,
+        }
+    }
+}
+
+impl MoveRules {
+    // Every move has to cost something. Otherwise equally cheap paths can run
+    // in circles, and best paths can no longer be counted in order of cost.
+    fn check(&self) -> Result<(), String> {
+        if self.step <= 0 {
+            return Err(format!("A step must cost at least 1, got {}", self.step));
+        }
+        if self.turn_left < 0 || self.turn_right < 0 || self.reverse < 0 {
+            return Err("Turns can't have a negative cost".to_string());
+        }
+        Ok(())
+    }
+}
+
+// A position together with the direction the reindeer is facing
+type State = ((i32, i32), Direction);
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct PathState {
     cost: i32,
     position: (i32, i32),
     facing: Direction,
-    path: Vec<(i32, i32)>,
 }
 
 impl Ord for PathState {
@@ -67,6 +122,123 @@ impl PartialOrd for PathState {
     }
 }
 
+// Result of the search: the best score and every tile on a best path, plus the
+// states on best paths with their cost and the states they're reached from
+struct BestPaths {
+    cost: i32,
+    tiles: HashSet<(i32, i32)>,
+    start: State,
+    end_states: Vec<State>,
+    costs: HashMap<State, i32>,
+    predecessors: HashMap<State, Vec<State>>,
+}
+
+impl BestPaths {
+    // How many distinct best paths pass through each tile
+    fn path_counts(&self) -> HashMap<(i32, i32), u64> {
+        let mut states: Vec<State> = self.costs.keys().copied().collect();
+        states.sort_by_key(|state| self.costs[state]);
+
+        // Paths from S into each state, in order of cost. Moves always cost
+        // something, so every predecessor has been counted by then.
+        let mut from_start: HashMap<State, u64> = HashMap::new();
+        for &state in &states {
+            let count = if state == self.start {
+                1
+            } else {
+                self.predecessors[&state]
+                    .iter()
+                    .fold(0u64, |total, previous| {
+                        total.saturating_add(from_start[previous])
+                    })
+            };
+            from_start.insert(state, count);
+        }
+
+        // Paths from each state on to E, pushed back to the predecessors
+        let mut to_end: HashMap<State, u64> =
+            self.end_states.iter().map(|&state| (state, 1)).collect();
+        for &state in states.iter().rev() {
+            let count = to_end.get(&state).copied().unwrap_or(0);
+            for &previous in self.predecessors.get(&state).into_iter().flatten() {
+                let entry = to_end.entry(previous).or_insert(0);
+                *entry = entry.saturating_add(count);
+            }
+        }
+
+        let mut counts = HashMap::new();
+        for state in states {
+            let through = from_start[&state].saturating_mul(to_end[&state]);
+            let entry = counts.entry(state.0).or_insert(0u64);
+            *entry = entry.saturating_add(through);
+        }
+        counts
+    }
+
+    // Up to `limit` of the best paths, found by walking back from E
+    fn routes(&self, limit: usize) -> Vec<Route> {
+        let mut routes = Vec::new();
+        let mut stack: Vec<Vec<State>> = self.end_states.iter().map(|&state| vec![state]).collect();
+
+        while let Some(reversed) = stack.pop() {
+            let last = reversed[reversed.len() - 1];
+            if last == self.start {
+                let mut states = reversed;
+                states.reverse();
+                routes.push(Route {
+                    cost: self.cost,
+                    states,
+                });
+                if routes.len() == limit {
+                    break;
+                }
+                continue;
+            }
+
+            for &previous in self.predecessors.get(&last).into_iter().flatten() {
+                let mut extended = reversed.clone();
+                extended.push(previous);
+                stack.push(extended);
+            }
+        }
+        routes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Route {
+    cost: i32,
+    states: Vec<State>,
+}
+
+impl Route {
+    // Tiles where the facing changes before the next step
+    fn turn_points(&self) -> Vec<(i32, i32)> {
+        self.states
+            .windows(2)
+            .filter(|pair| pair[0].1 != pair[1].1)
+            .map(|pair| pair[0].0)
+            .collect()
+    }
+
+    // A U-turn counts as two turns
+    fn turns(&self) -> usize {
+        self.states
+            .windows(2)
+            .map(|pair| {
+                let (from, to) = (pair[0].1, pair[1].1);
+                if from == to {
+                    0
+                } else if to == from.turn_left() || to == from.turn_right() {
+                    1
+                } else {
+                    2
+                }
+            })
+            .sum()
+    }
+}
+
 struct Maze {
     grid: Vec<Vec<char>>,
     start: (i32, i32),
@@ -76,6 +248,10 @@ struct Maze {
 impl Maze {
     fn from_file(path: &str) -> Self {
         let contents = fs::read_to_string(path).expect("Failed to read file");
+        Self::from_str(&contents)
+    }
+
+    fn from_str(contents: &str) -> Self {
         let grid: Vec<Vec<char>> = contents
             .lines()
             .map(|line| line.chars().collect())
@@ -105,141 +281,395 @@ impl Maze {
             && self.grid[pos.1 as usize][pos.0 as usize] != '#'
     }
 
-    fn find_paths(&self) -> Option<(i32, usize, Vec<Vec<(i32, i32)>>)> {
+    // Every state reachable in one move (turning as needed, then stepping) and its cost
+    fn moves(&self, (position, facing): State, rules: &MoveRules) -> Vec<(State, i32)> {
+        Direction::all_directions()
+            .into_iter()
+            .filter_map(|new_direction| {
+                let (dx, dy) = new_direction.delta();
+                let new_position = (position.0 + dx, position.1 + dy);
+                self.is_valid(new_position).then(|| {
+                    let cost = facing.turn_cost(&new_direction, rules) + rules.step;
+                    ((new_position, new_direction), cost)
+                })
+            })
+            .collect()
+    }
+
+    fn find_paths(&self, rules: &MoveRules) -> Option<BestPaths> {
+        rules.check().expect("Move rules should be valid");
+
         let mut heap = BinaryHeap::new();
-        let mut visited = HashMap::new();
+        let mut best_costs: HashMap<State, i32> = HashMap::new();
+        let mut predecessors: HashMap<State, Vec<State>> = HashMap::new();
         let mut min_cost = i32::MAX;
-        let mut unique_positions = std::collections::HashSet::new();
-        let mut all_min_cost_paths = Vec::new();
+        let mut end_states = Vec::new();
 
-        // Start facing East
-        let initial = PathState {
+        heap.push(PathState {
             cost: 0,
             position: self.start,
-            facing: Direction::East,
-            path: vec![self.start],
-        };
-
-        heap.push(initial.clone());
-        visited.insert((initial.position, initial.facing), 0);
+            facing: rules.start_facing,
+        });
+        best_costs.insert((self.start, rules.start_facing), 0);
 
         while let Some(PathState {
             cost,
             position,
             facing,
-            path,
         }) = heap.pop()
         {
-            // Skip paths that are already worse than our best
-            if min_cost != i32::MAX && cost > min_cost {
+            // Everything left is worse than our best
+            if cost > min_cost {
+                break;
+            }
+
+            // Skip stale heap entries
+            if cost > best_costs[&(position, facing)] {
                 continue;
             }
 
             if position == self.end {
-                if cost < min_cost {
-                    // Found a better path, reset everything
-                    min_cost = cost;
-                    all_min_cost_paths.clear();
-                    all_min_cost_paths.push(path.clone());
-                } else if cost == min_cost {
-                    // Found another path with same cost
-                    all_min_cost_paths.push(path.clone());
-                }
+                min_cost = cost;
+                end_states.push((position, facing));
                 continue;
             }
 
             // Try all directions
-            for new_direction in Direction::all_directions() {
-                let turn_cost = facing.turn_cost(&new_direction);
-                let (dx, dy) = new_direction.delta();
-                let new_position = (position.0 + dx, position.1 + dy);
-
-                if self.is_valid(new_position) {
-                    let new_cost = cost + turn_cost + 1;
-                    let key = (new_position, new_direction);
-
-                    // Allow paths with equal cost to continue
-                    if !visited.contains_key(&key) || new_cost <= *visited.get(&key).unwrap() {
-                        // Only update the visited cost if it's better
-                        if !visited.contains_key(&key) || new_cost < *visited.get(&key).unwrap() {
-                            visited.insert(key, new_cost);
-                        }
-                        let mut new_path = path.clone();
-                        new_path.push(new_position);
+            for (key, move_cost) in self.moves((position, facing), rules) {
+                let new_cost = cost + move_cost;
+                let (new_position, new_direction) = key;
+
+                match best_costs.get(&key) {
+                    Some(&best) if new_cost > best => {}
+                    Some(&best) if new_cost == best => {
+                        // Another way in at the same cost
+                        predecessors
+                            .entry(key)
+                            .or_default()
+                            .push((position, facing));
+                    }
+                    _ => {
+                        best_costs.insert(key, new_cost);
+                        predecessors.insert(key, vec![(position, facing)]);
                         heap.push(PathState {
                             cost: new_cost,
                             position: new_position,
                             facing: new_direction,
-                            path: new_path,
                         });
                     }
                 }
             }
         }
 
-        if min_cost != i32::MAX {
-            // After finding all minimum cost paths, collect all unique positions
-            unique_positions.clear();
+        if min_cost == i32::MAX {
+            return None;
+        }
 
-            // Explicitly ensure S and E are included
-            unique_positions.insert(self.start);
-            unique_positions.insert(self.end);
+        // Walk back from E through the predecessors to find every tile on a best path
+        let mut seen: HashSet<State> = end_states.iter().copied().collect();
+        let mut stack = end_states.clone();
+        while let Some(state) = stack.pop() {
+            for &previous in predecessors.get(&state).into_iter().flatten() {
+                if seen.insert(previous) {
+                    stack.push(previous);
+                }
+            }
+        }
+        let tiles = seen.iter().map(|&(position, _)| position).collect();
+        let costs = seen
+            .iter()
+            .map(|&state| (state, best_costs[&state]))
+            .collect();
+        predecessors.retain(|state, _| seen.contains(state));
+
+        Some(BestPaths {
+            cost: min_cost,
+            tiles,
+            start: (self.start, rules.start_facing),
+            end_states,
+            costs,
+            predecessors,
+        })
+    }
 
-            // Add all positions from all optimal paths
-            for path in &all_min_cost_paths {
-                unique_positions.extend(path.iter().cloned());
+    // Walls, tiles shaded by how many best paths use them, each route as a line
+    // with its turn points circled, and S and E labelled
+    fn render_svg(&self, best: &BestPaths, routes: &[Route]) -> String {
+        let cell = 10;
+        let height = self.grid.len() as i32;
+        let width = self.grid.first().map_or(0, |row| row.len()) as i32;
+        let center = |(x, y): (i32, i32)| (x * cell + cell / 2, y * cell + cell / 2);
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+            width * cell,
+            height * cell,
+            width * cell,
+            height * cell
+        );
+        svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"#f4f1e8\"/>\n");
+
+        for (y, row) in self.grid.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                if c == '#' {
+                    svg.push_str(&format!(
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#3b3b45\"/>\n",
+                        x as i32 * cell,
+                        y as i32 * cell,
+                        cell,
+                        cell
+                    ));
+                }
             }
+        }
 
-            // Double check S and E are included
-            if !unique_positions.contains(&self.start) || !unique_positions.contains(&self.end) {
-                println!("WARNING: Start or End position missing from unique positions!");
+        // From pale yellow for few paths to red for tiles every best path uses
+        let counts = best.path_counts();
+        let most = counts.values().copied().max().unwrap_or(1).max(1);
+        let mut tiles: Vec<_> = counts.into_iter().collect();
+        tiles.sort();
+        for ((x, y), count) in tiles {
+            let share = count as f64 / most as f64;
+            let green = (230.0 - 180.0 * share) as u8;
+            let blue = (150.0 - 130.0 * share) as u8;
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"rgb(240,{},{})\"><title>{} paths</title></rect>\n",
+                x * cell,
+                y * cell,
+                cell,
+                cell,
+                green,
+                blue,
+                count
+            ));
+        }
+
+        let palette = [
+            "#1f77b4", "#2ca02c", "#9467bd", "#17becf", "#8c564b", "#e377c2",
+        ];
+        for (i, route) in routes.iter().enumerate() {
+            let color = palette[i % palette.len()];
+            let points: Vec<String> = route
+                .states
+                .iter()
+                .map(|&(position, _)| {
+                    let (cx, cy) = center(position);
+                    format!("{},{}", cx, cy)
+                })
+                .collect();
+            svg.push_str(&format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\" stroke-opacity=\"0.7\"/>\n",
+                points.join(" "),
+                color
+            ));
+
+            for position in route.turn_points() {
+                let (cx, cy) = center(position);
+                svg.push_str(&format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>\n",
+                    cx,
+                    cy,
+                    cell / 3,
+                    color
+                ));
             }
-            Some((min_cost, unique_positions.len(), all_min_cost_paths))
-        } else {
-            None
         }
+
+        for (label, position) in [("S", self.start), ("E", self.end)] {
+            let (cx, cy) = center(position);
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"monospace\" font-weight=\"bold\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+                cx,
+                cy,
+                cell,
+                label
+            ));
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    fn route_cost(&self, states: &[State], rules: &MoveRules) -> i32 {
+        states
+            .windows(2)
+            .map(|pair| pair[0].1.turn_cost(&pair[1].1, rules) + rules.step)
+            .sum()
+    }
+
+    // Plain Dijkstra from `from` to E that avoids the given tiles and moves,
+    // returns the cost and states of the cheapest way there
+    fn shortest_route_from(
+        &self,
+        from: State,
+        rules: &MoveRules,
+        banned_tiles: &HashSet<(i32, i32)>,
+        banned_moves: &HashSet<(State, State)>,
+    ) -> Option<(i32, Vec<State>)> {
+        let mut heap = BinaryHeap::new();
+        let mut best_costs: HashMap<State, i32> = HashMap::new();
+        let mut came_from: HashMap<State, State> = HashMap::new();
+
+        heap.push(PathState {
+            cost: 0,
+            position: from.0,
+            facing: from.1,
+        });
+        best_costs.insert(from, 0);
+
+        while let Some(PathState {
+            cost,
+            position,
+            facing,
+        }) = heap.pop()
+        {
+            let state = (position, facing);
+            if cost > best_costs[&state] {
+                continue;
+            }
+
+            if position == self.end {
+                let mut states = vec![state];
+                let mut current = state;
+                while let Some(&previous) = came_from.get(&current) {
+                    states.push(previous);
+                    current = previous;
+                }
+                states.reverse();
+                return Some((cost, states));
+            }
+
+            for (next, move_cost) in self.moves(state, rules) {
+                // Coming back to where we started would loop
+                if next.0 == from.0
+                    || banned_tiles.contains(&next.0)
+                    || banned_moves.contains(&(state, next))
+                {
+                    continue;
+                }
+
+                let new_cost = cost + move_cost;
+                if best_costs.get(&next).is_none_or(|&best| new_cost < best) {
+                    best_costs.insert(next, new_cost);
+                    came_from.insert(next, state);
+                    heap.push(PathState {
+                        cost: new_cost,
+                        position: next.0,
+                        facing: next.1,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // Yen's algorithm over the (position, facing) graph: the k cheapest routes
+    // from S to E that never repeat a state. With the reindeer's costs that
+    // means no tile twice either, but when left turns are much cheaper than
+    // right ones a route may circle a block and cross its own path.
+    fn k_shortest_routes(&self, rules: &MoveRules, k: usize) -> Vec<Route> {
+        let start = (self.start, rules.start_facing);
+        let mut routes: Vec<Route> = Vec::new();
+        let mut candidates: Vec<Route> = Vec::new();
+        if k == 0 {
+            return routes;
+        }
+
+        let Some((cost, states)) =
+            self.shortest_route_from(start, rules, &HashSet::new(), &HashSet::new())
+        else {
+            return routes;
+        };
+        routes.push(Route { cost, states });
+
+        while routes.len() < k {
+            let previous = &routes[routes.len() - 1].states;
+
+            for i in 0..previous.len() - 1 {
+                let spur = previous[i];
+                let root = &previous[..=i];
+
+                // Don't take the next move of any found route sharing this root
+                let banned_moves: HashSet<(State, State)> = routes
+                    .iter()
+                    .filter(|route| route.states.len() > i + 1 && route.states[..=i] == *root)
+                    .map(|route| (route.states[i], route.states[i + 1]))
+                    .collect();
+
+                // Nor go back over the root itself
+                let banned_tiles: HashSet<(i32, i32)> =
+                    root[..i].iter().map(|&(position, _)| position).collect();
+
+                if let Some((spur_cost, spur_states)) =
+                    self.shortest_route_from(spur, rules, &banned_tiles, &banned_moves)
+                {
+                    let mut states = root[..i].to_vec();
+                    states.extend(spur_states);
+                    let candidate = Route {
+                        cost: self.route_cost(root, rules) + spur_cost,
+                        states,
+                    };
+
+                    if !candidates.contains(&candidate) && !routes.contains(&candidate) {
+                        candidates.push(candidate);
+                    }
+                }
+            }
+
+            // Move the cheapest candidate over, shorter routes first on ties
+            let Some(best) = (0..candidates.len())
+                .min_by_key(|&i| (candidates[i].cost, candidates[i].states.len()))
+            else {
+                break;
+            };
+            routes.push(candidates.swap_remove(best));
+        }
+
+        routes
     }
 }
 
 fn main() {
     let maze = Maze::from_file("input.txt");
-    match maze.find_paths() {
-        Some((points, unique_positions, paths)) => {
-            println!("Shortest path found: {} points", points);
+
+    // Usage: day-16 [svg output file]
+    let svg_path = std::env::args().nth(1);
+
+    // Movement costs can be easily modified here
+    let rules = MoveRules::default();
+    if let Err(message) = rules.check() {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    }
+
+    match maze.find_paths(&rules) {
+        Some(best) => {
+            println!("Shortest path found: {} points", best.cost);
 
             println!(
                 "Number of unique positions in shortest path(s): {} (including S and E)",
-                unique_positions
+                best.tiles.len()
             );
 
             // Print the maze with marked paths
-            let mut marked = vec![vec!['.'; maze.grid[0].len()]; maze.grid.len()];
-
-            // Mark walls
-            for y in 0..maze.grid.len() {
-                for x in 0..maze.grid[0].len() {
-                    if maze.grid[y][x] == '#' {
-                        marked[y][x] = '#';
-                    }
-                }
-            }
-
-            // Mark all positions that are part of any optimal path
-            let mut path_positions = std::collections::HashSet::new();
-            for path in paths {
-                for &(x, y) in &path {
-                    path_positions.insert((x, y));
-                }
-            }
+            let mut marked: Vec<Vec<char>> = maze
+                .grid
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|&cell| if cell == '#' { '#' } else { '.' })
+                        .collect()
+                })
+                .collect();
 
             // Verify S and E are included in paths
-            let start_included = path_positions.contains(&maze.start);
-            let end_included = path_positions.contains(&maze.end);
+            let start_included = best.tiles.contains(&maze.start);
+            let end_included = best.tiles.contains(&maze.end);
             println!("Start position included: {}", start_included);
             println!("End position included: {}", end_included);
 
             // Mark path positions with 'O'
-            for &(x, y) in &path_positions {
+            for &(x, y) in &best.tiles {
                 if marked[y as usize][x as usize] != '#' {
                     marked[y as usize][x as usize] = 'O';
                 }
@@ -254,7 +684,190 @@ fn main() {
             for row in marked {
                 println!("{}", row.iter().collect::<String>());
             }
+
+            if let Some(path) = &svg_path {
+                let routes = best.routes(6);
+                fs::write(path, maze.render_svg(&best, &routes)).expect("Failed to write SVG");
+                println!("Wrote {} with {} optimal route(s)", path, routes.len());
+            }
         }
         None => println!("No path found!"),
     }
+
+    // The runner-up routes and how far they are from the best
+    let k = 3;
+    let routes = maze.k_shortest_routes(&rules, k);
+    println!("\n{} cheapest routes:", routes.len());
+    for (i, route) in routes.iter().enumerate() {
+        println!(
+            "Route {}: {} points (+{}), {} tiles, {} turns",
+            i + 1,
+            route.cost,
+            route.cost - routes[0].cost,
+            route.states.len(),
+            route.turns()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST_EXAMPLE: &str = "###############
+#.......#....E#
+#.#.###.#.###.#
+#.....#.#...#.#
+#.###.#####.#.#
+#.#.#.......#.#
+#.#.#####.###.#
+#...........#.#
+###.#.#####.#.#
+#...#.....#.#.#
+#.#.#.###.#.#.#
+#.....#...#.#.#
+#.###.#.#.#.#.#
+#S..#.....#...#
+###############";
+
+    const SECOND_EXAMPLE: &str = "#################
+#...#...#...#..E#
+#.#.#.#.#.#.#.#.#
+#.#.#.#...#...#.#
+#.#.#.#.###.#.#.#
+#...#.#.#.....#.#
+#.#.#.#.#.#####.#
+#.#...#.#.#.....#
+#.#.#####.#.###.#
+#.#.#.......#...#
+#.#.###.#####.###
+#.#.#...#.....#.#
+#.#.#.#####.###.#
+#.#.#.........#.#
+#.#.#.#########.#
+#S#.............#
+#################";
+
+    #[test]
+    fn test_examples() {
+        let rules = MoveRules::default();
+
+        let best = Maze::from_str(FIRST_EXAMPLE).find_paths(&rules).unwrap();
+        assert_eq!((best.cost, best.tiles.len()), (7036, 45));
+
+        let best = Maze::from_str(SECOND_EXAMPLE).find_paths(&rules).unwrap();
+        assert_eq!((best.cost, best.tiles.len()), (11048, 64));
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        // Straight up from S to E, then right along the top
+        let maze = Maze::from_str(
+            "#####
+#..E#
+#.#.#
+#S..#
+#####",
+        );
+
+        // Starting north, going up first only needs a single right turn
+        let north = MoveRules {
+            start_facing: Direction::North,
+            ..MoveRules::default()
+        };
+        let best = maze.find_paths(&north).unwrap();
+        assert_eq!(best.cost, 1004);
+        assert!(best.tiles.contains(&(1, 1)));
+        assert!(!best.tiles.contains(&(3, 3)));
+
+        // With free left turns, going east first (right, then left) ties
+        let free_left = MoveRules {
+            turn_left: 0,
+            ..north
+        };
+        let best = maze.find_paths(&free_left).unwrap();
+        assert_eq!(best.cost, 1004);
+        assert!(best.tiles.contains(&(1, 1)) && best.tiles.contains(&(3, 3)));
+        assert_eq!(best.path_counts()[&maze.start], 2);
+
+        // Moves that cost nothing are rejected
+        assert!(MoveRules { step: 0, ..north }.check().is_err());
+        assert!(MoveRules {
+            reverse: -1,
+            ..north
+        }
+        .check()
+        .is_err());
+        assert!(free_left.check().is_ok());
+
+        let slow = MoveRules { step: 10, ..north };
+        assert_eq!(maze.find_paths(&slow).unwrap().cost, 1040);
+    }
+
+    #[test]
+    fn test_k_shortest_routes() {
+        let maze = Maze::from_str(FIRST_EXAMPLE);
+        let rules = MoveRules::default();
+        let routes = maze.k_shortest_routes(&rules, 10);
+
+        assert_eq!(routes.len(), 10);
+        assert_eq!(routes[0].cost, 7036);
+        assert_eq!(routes[0].turns(), 7);
+
+        // The example mentions three best paths, all of them come first
+        assert_eq!(routes.iter().filter(|route| route.cost == 7036).count(), 3);
+        assert!(routes.windows(2).all(|pair| pair[0].cost <= pair[1].cost));
+
+        for route in &routes {
+            assert_eq!(route.cost, maze.route_cost(&route.states, &rules));
+            assert_eq!(route.states[0].0, maze.start);
+            assert_eq!(route.states[route.states.len() - 1].0, maze.end);
+
+            let tiles: HashSet<(i32, i32)> = route.states.iter().map(|&(tile, _)| tile).collect();
+            assert_eq!(tiles.len(), route.states.len());
+        }
+
+        assert!(maze.k_shortest_routes(&rules, 0).is_empty());
+
+        // Free left turns make circling the block cheaper than one right turn,
+        // so the route crosses its own tile but still repeats no state.
+        let maze = Maze::from_str("#####\n#...#\n#S..#\n##E##\n#####");
+        let lefty = MoveRules {
+            turn_left: 0,
+            ..rules
+        };
+        let routes = maze.k_shortest_routes(&lefty, 1);
+        assert_eq!(routes[0].cost, 6);
+        let tiles: HashSet<(i32, i32)> = routes[0].states.iter().map(|&(tile, _)| tile).collect();
+        let states: HashSet<State> = routes[0].states.iter().copied().collect();
+        assert_eq!(tiles.len(), routes[0].states.len() - 1);
+        assert_eq!(states.len(), routes[0].states.len());
+    }
+
+    #[test]
+    fn test_path_counts_and_svg() {
+        let maze = Maze::from_str(FIRST_EXAMPLE);
+        let best = maze.find_paths(&MoveRules::default()).unwrap();
+
+        // Three best paths, all of them through S and E
+        let counts = best.path_counts();
+        assert_eq!(counts.len(), best.tiles.len());
+        assert_eq!(counts[&maze.start], 3);
+        assert_eq!(counts[&maze.end], 3);
+
+        let routes = best.routes(10);
+        assert_eq!(routes.len(), 3);
+        for route in &routes {
+            assert_eq!(
+                route.cost,
+                maze.route_cost(&route.states, &MoveRules::default())
+            );
+            assert_eq!(route.turn_points().len(), route.turns());
+        }
+
+        let svg = maze.render_svg(&best, &routes);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<polyline").count(), 3);
+        assert!(svg.contains(">S</text>") && svg.contains(">E</text>"));
+    }
 }
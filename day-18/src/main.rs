@@ -1,7 +1,7 @@
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, HashSet};
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::io;
+use std::path::Path;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 struct Point {
@@ -36,34 +36,56 @@ fn manhattan_distance(p1: &Point, p2: &Point) -> i32 {
     (p1.x - p2.x).abs() + (p1.y - p2.y).abs()
 }
 
-fn read_danger_points() -> io::Result<Vec<Point>> {
-    let file = File::open("input.txt")?;
-    let reader = io::BufReader::new(file);
+fn parse_danger_points(input: &str) -> Vec<Point> {
     let mut points = Vec::new();
 
-    for line in reader.lines() {
-        let line = line?;
-        if let Some((x, y)) = line.split_once(',') {
+    for line in input.lines() {
+        if let Some((x, y)) = line.trim().split_once(',') {
             if let (Ok(x), Ok(y)) = (x.parse(), y.parse()) {
                 points.push(Point { x, y });
             }
         }
     }
-    Ok(points)
+    points
+}
+
+fn read_danger_points(path: &str) -> io::Result<Vec<Point>> {
+    Ok(parse_danger_points(&std::fs::read_to_string(path)?))
+}
+
+// The memory space is as large as the furthest byte: 6 for the example, 70 for real inputs
+fn detect_grid_size(danger_points: &[Point]) -> i32 {
+    danger_points
+        .iter()
+        .map(|p| p.x.max(p.y))
+        .max()
+        .unwrap_or(0)
+}
+
+// How many bytes have fallen before part 1 looks for a path. The puzzle says
+// 12 for the example grid and 1024 for the real one. Other grids get half of
+// their bytes, leaving the rest for part 2 to search.
+fn default_byte_count(size: i32, bytes: usize) -> usize {
+    match size {
+        6 => 12,
+        70 => 1024,
+        _ => bytes / 2,
+    }
+    .min(bytes)
 }
 
 fn is_valid_point(p: &Point, size: i32) -> bool {
-    p.x >= 0 && p.x <= size && p.y >= 0 && p.y <= size // Changed to include 70
+    p.x >= 0 && p.x <= size && p.y >= 0 && p.y <= size // Inclusive of size
 }
 
-fn find_shortest_path(size: i32, danger_points: Vec<Point>) -> Option<i32> {
+// A* that keeps parent pointers, returns every point from start to goal
+fn find_route(size: i32, dangers: &HashSet<Point>) -> Option<Vec<Point>> {
     let start = Point { x: 0, y: 0 };
     let goal = Point { x: size, y: size };
     let mut open_set = BinaryHeap::new();
     let mut closed_set = HashSet::new();
-
-    // Only use the first 1024 danger points
-    let dangers: HashSet<Point> = danger_points.iter().take(1024).copied().collect();
+    let mut best_steps: HashMap<Point, i32> = HashMap::new();
+    let mut came_from: HashMap<Point, Point> = HashMap::new();
 
     // Initialize with starting point
     open_set.push(Node {
@@ -71,13 +93,125 @@ fn find_shortest_path(size: i32, danger_points: Vec<Point>) -> Option<i32> {
         steps: 0,
         f_score: manhattan_distance(&start, &goal),
     });
+    best_steps.insert(start, 0);
 
     // Possible moves: up, down, left, right
     let directions = [(0, -1), (0, 1), (-1, 0), (1, 0)];
 
+    while let Some(current) = open_set.pop() {
+        if current.point == goal {
+            // Follow the parents back to the start
+            let mut route = vec![goal];
+            let mut point = goal;
+            while let Some(&parent) = came_from.get(&point) {
+                route.push(parent);
+                point = parent;
+            }
+            route.reverse();
+            return Some(route);
+        }
+
+        if !closed_set.insert(current.point) {
+            continue;
+        }
+
+        for (dx, dy) in directions.iter() {
+            let next = Point {
+                x: current.point.x + dx,
+                y: current.point.y + dy,
+            };
+            let steps = current.steps + 1;
+
+            if !is_valid_point(&next, size)
+                || closed_set.contains(&next)
+                || dangers.contains(&next)
+                || best_steps.get(&next).is_some_and(|&best| best <= steps)
+            {
+                continue;
+            }
+
+            best_steps.insert(next, steps);
+            came_from.insert(next, current.point);
+            open_set.push(Node {
+                point: next,
+                steps,
+                f_score: steps + manhattan_distance(&next, &goal),
+            });
+        }
+    }
+    None
+}
+
+// '#' for corrupted cells, 'O' for the route and 'X' for the blocking byte
+fn render(size: i32, dangers: &HashSet<Point>, route: &[Point], blocking: Option<Point>) -> String {
+    let route: HashSet<Point> = route.iter().copied().collect();
+    let mut output = String::new();
+
+    for y in 0..=size {
+        for x in 0..=size {
+            let point = Point { x, y };
+            let c = if Some(point) == blocking {
+                'X'
+            } else if dangers.contains(&point) {
+                '#'
+            } else if route.contains(&point) {
+                'O'
+            } else {
+                '.'
+            };
+            output.push(c);
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// Writes a rendered grid as a binary PPM image, each cell scale x scale pixels
+fn write_ppm(path: &Path, rendered: &str, scale: usize) -> io::Result<()> {
+    let rows: Vec<&str> = rendered.lines().collect();
+    let height = rows.len() * scale;
+    let width = rows.first().map_or(0, |row| row.len()) * scale;
+
+    let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for row in &rows {
+        let pixels: Vec<[u8; 3]> = row
+            .chars()
+            .map(|c| match c {
+                '#' => [90, 90, 90],
+                'O' => [40, 200, 80],
+                'X' => [230, 40, 40],
+                _ => [15, 15, 35],
+            })
+            .collect();
+        for _ in 0..scale {
+            for pixel in &pixels {
+                for _ in 0..scale {
+                    data.extend_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    std::fs::write(path, data)
+}
+
+fn path_exists(size: i32, dangers: &HashSet<Point>) -> bool {
+    let start = Point { x: 0, y: 0 };
+    let goal = Point { x: size, y: size };
+    let mut open_set = BinaryHeap::new();
+    let mut closed_set = HashSet::new();
+
+    open_set.push(Node {
+        point: start,
+        steps: 0,
+        f_score: manhattan_distance(&start, &goal),
+    });
+
+    let directions = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+
     while let Some(current) = open_set.pop() {
         if current.point.x == goal.x && current.point.y == goal.y {
-            return Some(current.steps);
+            return true;
         }
 
         if !closed_set.insert(current.point) {
@@ -104,97 +238,315 @@ fn find_shortest_path(size: i32, danger_points: Vec<Point>) -> Option<i32> {
             open_set.push(next_node);
         }
     }
-    None
+    false
 }
 
-fn find_blocking_point(size: i32, danger_points: &[Point]) -> Option<Point> {
-    let mut current_dangers: HashSet<Point> = danger_points.iter().take(1024).copied().collect();
+// Returns the index and position of the first byte that cuts off the exit
+fn find_blocking_point(
+    size: i32,
+    danger_points: &[Point],
+    byte_count: usize,
+) -> Option<(usize, Point)> {
+    let mut current_dangers: HashSet<Point> =
+        danger_points.iter().take(byte_count).copied().collect();
 
     // Try adding each subsequent danger point until no path exists
-    for (idx, &point) in danger_points.iter().skip(1024).enumerate() {
+    for (idx, &point) in danger_points.iter().enumerate().skip(byte_count) {
         current_dangers.insert(point);
 
-        // Try to find any path with current danger points
-        let start = Point { x: 0, y: 0 };
-        let goal = Point { x: size, y: size };
-        let mut open_set = BinaryHeap::new();
-        let mut closed_set = HashSet::new();
-
-        open_set.push(Node {
-            point: start,
-            steps: 0,
-            f_score: manhattan_distance(&start, &goal),
-        });
-
-        let directions = [(0, -1), (0, 1), (-1, 0), (1, 0)];
-        let mut path_exists = false;
-
-        while let Some(current) = open_set.pop() {
-            if current.point.x == goal.x && current.point.y == goal.y {
-                path_exists = true;
-                break;
-            }
+        if !path_exists(size, &current_dangers) {
+            return Some((idx, point));
+        }
+    }
+    None
+}
 
-            if !closed_set.insert(current.point) {
-                continue;
-            }
+// Same answer as `find_blocking_point`, but binary searches over how many bytes
+// have fallen since more bytes can only ever close off paths
+fn find_blocking_point_binary(
+    size: i32,
+    danger_points: &[Point],
+    byte_count: usize,
+) -> Option<(usize, Point)> {
+    let blocked = |fallen: usize| {
+        let dangers: HashSet<Point> = danger_points.iter().take(fallen).copied().collect();
+        !path_exists(size, &dangers)
+    };
+
+    // The first blocked prefix length lies in low..=high, high meaning none
+    let mut low = byte_count + 1;
+    let mut high = danger_points.len() + 1;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if blocked(mid) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
 
-            for (dx, dy) in directions.iter() {
-                let next = Point {
-                    x: current.point.x + dx,
-                    y: current.point.y + dy,
-                };
-
-                if !is_valid_point(&next, size)
-                    || closed_set.contains(&next)
-                    || current_dangers.contains(&next)
-                {
-                    continue;
-                }
+    if low > danger_points.len() {
+        None
+    } else {
+        Some((low - 1, danger_points[low - 1]))
+    }
+}
 
-                let next_node = Node {
-                    point: next,
-                    steps: current.steps + 1,
-                    f_score: (current.steps + 1) + manhattan_distance(&next, &goal),
-                };
+// Bytes keep falling while we walk: byte i lands at time step i, and a cell
+// can't be entered (or waited on) once it is corrupted at that time. Since
+// corruption is permanent, waiting never gets us out sooner, but it is
+// searched when allowed so the two models can be compared.
+fn find_escape_path(size: i32, danger_points: &[Point], allow_wait: bool) -> Option<i32> {
+    let start = Point { x: 0, y: 0 };
+    let goal = Point { x: size, y: size };
 
-                open_set.push(next_node);
-            }
+    // Time at which each cell becomes corrupted, only the first byte counts
+    let mut fall_times: HashMap<Point, i32> = HashMap::new();
+    for (time, &point) in danger_points.iter().enumerate() {
+        fall_times.entry(point).or_insert(time as i32);
+    }
+    let safe_at = |point: &Point, time: i32| fall_times.get(point).is_none_or(|&t| t > time);
+
+    if !safe_at(&start, 0) {
+        return None;
+    }
+
+    // After the last byte the grid no longer changes, so later times are equivalent
+    let last_fall = danger_points.len() as i32;
+
+    let mut open_set = BinaryHeap::new();
+    let mut closed_set = HashSet::new();
+
+    open_set.push(Node {
+        point: start,
+        steps: 0,
+        f_score: manhattan_distance(&start, &goal),
+    });
+
+    // Possible moves: up, down, left, right, and optionally staying put
+    let mut directions = vec![(0, -1), (0, 1), (-1, 0), (1, 0)];
+    if allow_wait {
+        directions.push((0, 0));
+    }
+
+    while let Some(current) = open_set.pop() {
+        if current.point == goal {
+            return Some(current.steps);
         }
 
-        if !path_exists {
-            println!(
-                "Found blocking point at index {}: ({}, {})",
-                idx + 1024,
-                point.x,
-                point.y
-            );
-            return Some(point);
+        if !closed_set.insert((current.point, current.steps.min(last_fall))) {
+            continue;
+        }
+
+        for (dx, dy) in directions.iter() {
+            let next = Point {
+                x: current.point.x + dx,
+                y: current.point.y + dy,
+            };
+            let time = current.steps + 1;
+
+            if !is_valid_point(&next, size)
+                || !safe_at(&next, time)
+                || closed_set.contains(&(next, time.min(last_fall)))
+            {
+                continue;
+            }
+
+            open_set.push(Node {
+                point: next,
+                steps: time,
+                f_score: time + manhattan_distance(&next, &goal),
+            });
         }
     }
     None
 }
 
 fn main() -> io::Result<()> {
-    let danger_points = read_danger_points()?;
-    let grid_size = 70; // This now means 0-70 inclusive
+    // Usage: day-18 [input file] [grid size] [byte count] [image dir]
+    // Grid size and byte count are detected from the input when left out, and
+    // the renderings are only saved as PPM images when a directory is given
+    let args: Vec<String> = std::env::args().collect();
+    let path = args.get(1).map_or("input.txt", |arg| arg.as_str());
+    let danger_points = read_danger_points(path)?;
+
+    // Coordinates run from 0 to grid_size inclusive
+    let grid_size = match args.get(2) {
+        Some(arg) => arg.parse().expect("Grid size should be a number"),
+        None => detect_grid_size(&danger_points),
+    };
+    let byte_count = match args.get(3) {
+        Some(arg) => arg.parse().expect("Byte count should be a number"),
+        None => default_byte_count(grid_size, danger_points.len()),
+    };
+    println!(
+        "Memory space 0-{} with {} fallen bytes",
+        grid_size, byte_count
+    );
+    if byte_count >= danger_points.len() {
+        println!(
+            "Warning: all {} bytes have fallen already, part 2 has none left to try",
+            danger_points.len()
+        );
+    }
 
     // Part 1: Find the shortest path
 
-    match find_shortest_path(grid_size, danger_points.clone()) {
-        Some(steps) => println!("Shortest path found: {} steps", steps),
+    let image_dir = args.get(4).map(Path::new);
+    if let Some(dir) = image_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let dangers: HashSet<Point> = danger_points.iter().take(byte_count).copied().collect();
+    match find_route(grid_size, &dangers) {
+        Some(route) => {
+            let rendered = render(grid_size, &dangers, &route, None);
+            println!("{}", rendered);
+            println!("Shortest path found: {} steps", route.len() - 1);
+            if let Some(dir) = image_dir {
+                write_ppm(&dir.join("part1.ppm"), &rendered, 8)?;
+            }
+        }
         None => println!("No valid path found!"),
     }
 
     // Part 2: Find the blocking point that makes all paths impossible
-    if let Some(blocking_point) = find_blocking_point(grid_size, &danger_points) {
+    // The linear search reruns A* after every byte, binary search needs about a dozen runs
+    let use_binary_search = true;
+    let blocking = if use_binary_search {
+        find_blocking_point_binary(grid_size, &danger_points, byte_count)
+    } else {
+        find_blocking_point(grid_size, &danger_points, byte_count)
+    };
+
+    if let Some((index, blocking_point)) = blocking {
+        // Show the last route that was still open and the byte that closes it
+        let dangers: HashSet<Point> = danger_points.iter().take(index + 1).copied().collect();
+        let open: HashSet<Point> = danger_points.iter().take(index).copied().collect();
+        let route = find_route(grid_size, &open).unwrap_or_default();
+        let rendered = render(grid_size, &dangers, &route, Some(blocking_point));
+        println!("{}", rendered);
+        if let Some(dir) = image_dir {
+            write_ppm(&dir.join("part2.ppm"), &rendered, 8)?;
+        }
+
         println!(
-            "Part 2 - Blocking point: ({}, {})",
-            blocking_point.x, blocking_point.y
+            "Part 2 - Blocking point at index {}: ({}, {})",
+            index, blocking_point.x, blocking_point.y
         );
     } else {
         println!("No blocking point found!");
     }
 
+    // Escaping while the bytes are still falling, one per step
+    for allow_wait in [false, true] {
+        match find_escape_path(grid_size, &danger_points, allow_wait) {
+            Some(steps) => println!(
+                "Escape while falling (waiting {}): {} steps",
+                if allow_wait { "allowed" } else { "not allowed" },
+                steps
+            ),
+            None => println!(
+                "Escape while falling (waiting {}): impossible",
+                if allow_wait { "allowed" } else { "not allowed" }
+            ),
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "5,4\n4,2\n4,5\n3,0\n2,1\n6,3\n2,4\n1,5\n0,6\n3,3\n2,6\n5,1\n1,2\n5,5\n2,5\n6,5\n1,4\n0,4\n6,4\n1,1\n6,1\n1,0\n0,5\n1,6\n2,0";
+
+    #[test]
+    fn test_example() {
+        let danger_points = parse_danger_points(EXAMPLE);
+        let size = detect_grid_size(&danger_points);
+        let byte_count = default_byte_count(size, danger_points.len());
+        assert_eq!((size, byte_count), (6, 12));
+
+        let dangers: HashSet<Point> = danger_points.iter().take(byte_count).copied().collect();
+        assert_eq!(
+            find_route(size, &dangers).map(|route| route.len() - 1),
+            Some(22)
+        );
+        assert_eq!(
+            find_blocking_point(size, &danger_points, byte_count),
+            Some((20, Point { x: 6, y: 1 }))
+        );
+    }
+
+    #[test]
+    fn test_default_byte_count() {
+        assert_eq!(default_byte_count(70, 3450), 1024);
+        // Other grids leave half their bytes for part 2
+        assert_eq!(default_byte_count(20, 300), 150);
+        // Never more bytes than the input has
+        assert_eq!(default_byte_count(6, 5), 5);
+        assert_eq!(default_byte_count(70, 900), 900);
+    }
+
+    #[test]
+    fn test_binary_search_matches_linear() {
+        let danger_points = parse_danger_points(EXAMPLE);
+
+        for byte_count in 0..=danger_points.len() {
+            assert_eq!(
+                find_blocking_point_binary(6, &danger_points, byte_count),
+                find_blocking_point(6, &danger_points, byte_count)
+            );
+        }
+    }
+
+    #[test]
+    fn test_escape_while_falling() {
+        // The whole middle row falls, but only after we've already crossed it
+        let late = [
+            Point { x: 2, y: 0 },
+            Point { x: 2, y: 0 },
+            Point { x: 2, y: 0 },
+            Point { x: 2, y: 0 },
+            Point { x: 2, y: 0 },
+            Point { x: 0, y: 1 },
+            Point { x: 1, y: 1 },
+            Point { x: 2, y: 1 },
+        ];
+        assert_eq!(find_route(2, &late.iter().copied().collect()), None);
+        assert_eq!(find_escape_path(2, &late, false), Some(4));
+        assert_eq!(find_escape_path(2, &late, true), Some(4));
+
+        // Falling right away cuts us off either way
+        let early = &late[5..];
+        assert_eq!(find_escape_path(2, early, false), None);
+        assert_eq!(find_escape_path(2, early, true), None);
+    }
+
+    #[test]
+    fn test_render_example() {
+        let danger_points = parse_danger_points(EXAMPLE);
+        let dangers: HashSet<Point> = danger_points.iter().take(12).copied().collect();
+        let route = find_route(6, &dangers).unwrap();
+
+        assert_eq!(route.len(), 23);
+        assert_eq!(route[0], Point { x: 0, y: 0 });
+        assert_eq!(route[22], Point { x: 6, y: 6 });
+        assert!(route
+            .windows(2)
+            .all(|pair| manhattan_distance(&pair[0], &pair[1]) == 1));
+
+        let rendered = render(6, &dangers, &route, None);
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert_eq!(rows.len(), 7);
+        assert_eq!(rows[0].chars().next(), Some('O'));
+        assert_eq!(rows[4].chars().nth(5), Some('#'));
+        assert_eq!(rendered.matches('O').count(), 23);
+
+        // The blocking byte is drawn over whatever is underneath it
+        let rendered = render(6, &dangers, &route, Some(Point { x: 6, y: 1 }));
+        assert_eq!(rendered.lines().nth(1).unwrap().chars().nth(6), Some('X'));
+    }
+}
@@ -1,11 +1,75 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::read_to_string;
 
+// A cheat disables collision from `start` to `end` and saves `saved` picoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Cheat {
+    saved: usize,
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+#[derive(Debug)]
+struct CheatReport {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatReport {
+    fn new(mut cheats: Vec<Cheat>) -> Self {
+        cheats.sort();
+        CheatReport { cheats }
+    }
+
+    // Number of cheats for each amount of picoseconds saved
+    fn histogram(&self) -> BTreeMap<usize, usize> {
+        let mut counts = BTreeMap::new();
+        for cheat in &self.cheats {
+            *counts.entry(cheat.saved).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn total(&self) -> usize {
+        self.cheats.len()
+    }
+}
+
+// Lists the savings the same way the puzzle's examples do
+impl fmt::Display for CheatReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (saved, count) in self.histogram() {
+            if count == 1 {
+                writeln!(f, "There is one cheat that saves {} picoseconds.", saved)?;
+            } else {
+                writeln!(
+                    f,
+                    "There are {} cheats that save {} picoseconds.",
+                    count, saved
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// How cheats are searched for. `Scan` checks a diamond around every open cell,
+// `PathIndex` compares pairs of positions along the single track instead.
+// Both assume a single track; `General` handles branches, loops and dead ends.
+#[derive(Debug, Clone, Copy)]
+enum CheatSearch {
+    Scan,
+    PathIndex { threads: usize },
+    General,
+}
+
 #[derive(Debug)]
 struct Grid {
     cells: Vec<Vec<char>>,
     width: usize,
     height: usize,
     start: (usize, usize),
+    end: (usize, usize),
     path: Vec<(usize, usize)>,
 }
 
@@ -37,7 +101,45 @@ impl Grid {
         distances
     }
 
-    fn find_multi_shortcuts(&self, max_steps: usize) -> Vec<((usize, usize), usize)> {
+    // Open cells within max_steps Manhattan distance, with that distance
+    fn cells_within(
+        &self,
+        (x, y): (usize, usize),
+        max_steps: usize,
+    ) -> Vec<((usize, usize), usize)> {
+        let mut cells = Vec::new();
+
+        for dx in -(max_steps as i32)..=max_steps as i32 {
+            for dy in -(max_steps as i32)..=max_steps as i32 {
+                // Check if within max_steps Manhattan distance
+                if dx.abs() + dy.abs() > max_steps as i32 {
+                    continue;
+                }
+
+                let end_x = x as i32 + dx;
+                let end_y = y as i32 + dy;
+
+                if end_x < 0
+                    || end_y < 0
+                    || end_x >= self.width as i32
+                    || end_y >= self.height as i32
+                {
+                    continue;
+                }
+
+                let end_x = end_x as usize;
+                let end_y = end_y as usize;
+
+                if self.cells[end_y][end_x] != '#' {
+                    cells.push(((end_x, end_y), (dx.abs() + dy.abs()) as usize));
+                }
+            }
+        }
+
+        cells
+    }
+
+    fn find_multi_shortcuts(&self, max_steps: usize, min_saving: usize) -> Vec<Cheat> {
         let distances = self.calculate_distances(self.start);
         let mut shortcuts = Vec::new();
 
@@ -54,66 +156,189 @@ impl Grid {
                 }
 
                 // Try all positions within max_steps Manhattan distance
-                for dx in -(max_steps as i32)..=max_steps as i32 {
-                    for dy in -(max_steps as i32)..=max_steps as i32 {
-                        // Check if within max_steps Manhattan distance
-                        if dx.abs() + dy.abs() > max_steps as i32 {
-                            continue;
-                        }
+                for ((end_x, end_y), cheat_len) in self.cells_within((x, y), max_steps) {
+                    let end_dist = distances[end_y][end_x];
+                    if end_dist == usize::MAX {
+                        continue;
+                    }
 
-                        let end_x = x as i32 + dx;
-                        let end_y = y as i32 + dy;
+                    let shortcut_dist = start_dist + cheat_len;
+                    if shortcut_dist < end_dist && end_dist - shortcut_dist >= min_saving {
+                        shortcuts.push(Cheat {
+                            saved: end_dist - shortcut_dist,
+                            start: (x, y),
+                            end: (end_x, end_y),
+                        });
+                    }
+                }
+            }
+        }
 
-                        if end_x < 0
-                            || end_y < 0
-                            || end_x >= self.width as i32
-                            || end_y >= self.height as i32
-                        {
-                            continue;
-                        }
+        shortcuts
+    }
 
-                        let end_x = end_x as usize;
-                        let end_y = end_y as usize;
+    // Works on any maze: a cheat from a to b gives a route of
+    // dist(S, a) + cheat + dist(b, E), compared against the true shortest path
+    fn find_general_shortcuts(&self, max_steps: usize, min_saving: usize) -> Vec<Cheat> {
+        let from_start = self.calculate_distances(self.start);
+        let to_end = self.calculate_distances(self.end);
+        let mut shortcuts = Vec::new();
 
-                        if self.cells[end_y][end_x] == '#' {
-                            continue;
-                        }
+        let best = from_start[self.end.1][self.end.0];
+        if best == usize::MAX {
+            return shortcuts;
+        }
 
-                        let end_dist = distances[end_y][end_x];
-                        if end_dist == usize::MAX {
-                            continue;
-                        }
+        for (y, row) in from_start.iter().enumerate() {
+            for (x, &start_dist) in row.iter().enumerate() {
+                if self.cells[y][x] == '#' || start_dist == usize::MAX {
+                    continue;
+                }
 
-                        let shortcut_dist = start_dist + (dx.abs() + dy.abs()) as usize;
-                        if shortcut_dist < end_dist && end_dist - shortcut_dist >= 100 {
-                            shortcuts.push(((end_x, end_y), end_dist - shortcut_dist));
-                        }
+                for ((end_x, end_y), cheat_len) in self.cells_within((x, y), max_steps) {
+                    let end_dist = to_end[end_y][end_x];
+                    if end_dist == usize::MAX {
+                        continue;
                     }
+
+                    let cheated = start_dist + cheat_len + end_dist;
+                    if cheated < best && best - cheated >= min_saving {
+                        shortcuts.push(Cheat {
+                            saved: best - cheated,
+                            start: (x, y),
+                            end: (end_x, end_y),
+                        });
+                    }
+                }
+            }
+        }
+
+        shortcuts
+    }
+
+    // True when every open cell lies on the shortest path, i.e. there are no
+    // branches, loops or dead ends and path indices are distances from S
+    fn is_single_track(&self) -> bool {
+        let open = self.cells.iter().flatten().filter(|&&c| c != '#').count();
+        !self.path.is_empty() && open == self.path.len()
+    }
+
+    // Shortest route from S to E, empty if E can't be reached
+    fn shortest_path(&self) -> Vec<(usize, usize)> {
+        let distances = self.calculate_distances(self.start);
+        if distances[self.end.1][self.end.0] == usize::MAX {
+            return Vec::new();
+        }
+
+        // Walk back from E, always stepping to a cell one closer to S
+        let mut path = vec![self.end];
+        let mut current = self.end;
+        while current != self.start {
+            let (x, y) = current;
+            let dist = distances[y][x];
+            for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
+                let new_x = (x as i32 + dx) as usize;
+                let new_y = (y as i32 + dy) as usize;
+
+                if new_x < self.width && new_y < self.height && distances[new_y][new_x] == dist - 1
+                {
+                    current = (new_x, new_y);
+                    break;
                 }
             }
+            path.push(current);
+        }
+
+        path.reverse();
+        path
+    }
+
+    fn find_cheats(&self, search: CheatSearch, max_steps: usize, min_saving: usize) -> Vec<Cheat> {
+        match search {
+            CheatSearch::Scan => self.find_multi_shortcuts(max_steps, min_saving),
+            CheatSearch::PathIndex { threads } => {
+                self.find_path_shortcuts(max_steps, min_saving, threads)
+            }
+            CheatSearch::General => self.find_general_shortcuts(max_steps, min_saving),
+        }
+    }
+
+    // On a single track the path index is the distance from the start, so a
+    // cheat from index i to index j saves j - i minus the cheat length
+    fn path_shortcuts_from(&self, from: usize, max_steps: usize, min_saving: usize) -> Vec<Cheat> {
+        let mut shortcuts = Vec::new();
+        let (x, y) = self.path[from];
+
+        // Anything closer along the track can't save enough, and a cheat has
+        // to save something to count at all
+        let mut to = from + min_saving.max(1) + 1;
+        while to < self.path.len() {
+            let (end_x, end_y) = self.path[to];
+            let cheat_len = x.abs_diff(end_x) + y.abs_diff(end_y);
+
+            if cheat_len > max_steps {
+                // Each step along the track changes the distance by at most one
+                to += cheat_len - max_steps;
+                continue;
+            }
+
+            let saved = to - from - cheat_len;
+            if saved > 0 && saved >= min_saving {
+                shortcuts.push(Cheat {
+                    saved,
+                    start: (x, y),
+                    end: (end_x, end_y),
+                });
+            }
+            to += 1;
         }
 
         shortcuts
     }
 
+    fn find_path_shortcuts(
+        &self,
+        max_steps: usize,
+        min_saving: usize,
+        threads: usize,
+    ) -> Vec<Cheat> {
+        if threads <= 1 {
+            return (0..self.path.len())
+                .flat_map(|from| self.path_shortcuts_from(from, max_steps, min_saving))
+                .collect();
+        }
+
+        // Interleave start indices so every thread gets a similar amount of work
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|offset| {
+                    scope.spawn(move || {
+                        (offset..self.path.len())
+                            .step_by(threads)
+                            .flat_map(|from| self.path_shortcuts_from(from, max_steps, min_saving))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("Cheat search thread panicked"))
+                .collect()
+        })
+    }
+
     fn new(input: &str) -> Self {
         let mut cells = Vec::new();
         let mut start = (0, 0);
         let mut end = (0, 0);
-        let mut path = Vec::new();
-        let mut current = (0, 0);
-        let mut found_path = false;
 
         // Parse the grid
         for (y, line) in input.lines().enumerate() {
             let mut row = Vec::new();
             for (x, ch) in line.chars().enumerate() {
                 match ch {
-                    'S' => {
-                        start = (x, y);
-                        current = (x, y);
-                        path.push((x, y));
-                    }
+                    'S' => start = (x, y),
                     'E' => end = (x, y),
                     _ => (),
                 }
@@ -122,42 +347,22 @@ impl Grid {
             cells.push(row);
         }
 
-        // Find the single path from S to E
-        while !found_path {
-            let (x, y) = current;
-            // Try each direction
-            for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
-                let new_x = (x as i32 + dx) as usize;
-                let new_y = (y as i32 + dy) as usize;
-
-                if new_x < cells[0].len()
-                    && new_y < cells.len()
-                    && cells[new_y][new_x] != '#'
-                    && !path.contains(&(new_x, new_y))
-                {
-                    path.push((new_x, new_y));
-                    current = (new_x, new_y);
-                    if current == end {
-                        found_path = true;
-                    }
-                    break;
-                }
-            }
-        }
-
         let height = cells.len();
         let width = cells.first().map_or(0, |row| row.len());
 
-        Grid {
+        let mut grid = Grid {
             cells,
             width,
             height,
             start,
-            path,
-        }
+            end,
+            path: Vec::new(),
+        };
+        grid.path = grid.shortest_path();
+        grid
     }
 
-    fn find_wall_shortcuts(&self) -> Vec<((usize, usize), usize)> {
+    fn find_wall_shortcuts(&self, min_saving: usize) -> Vec<Cheat> {
         let mut shortcuts = Vec::new();
 
         // For each position in the path
@@ -188,10 +393,14 @@ impl Grid {
                         {
                             // Calculate how many steps this shortcut would save
                             let original_length = dest_idx - path_idx;
-                            let saved_steps = original_length - 1; // -1 because we're adding one step through wall
-
-                            if saved_steps >= 100 {
-                                shortcuts.push(((wall_x, wall_y), saved_steps));
+                            let saved_steps = original_length - 2; // -2 because the cheat itself takes two steps
+
+                            if saved_steps > 0 && saved_steps >= min_saving {
+                                shortcuts.push(Cheat {
+                                    saved: saved_steps,
+                                    start: (x, y),
+                                    end: dest_pos,
+                                });
                             }
                         }
                     }
@@ -207,29 +416,182 @@ fn main() {
     let input = read_to_string("input.txt").expect("Failed to read input file");
     let grid = Grid::new(&input);
 
-    // Part 1: Single wall shortcuts
-    let shortcuts = grid.find_wall_shortcuts();
+    // Cheat rules can be easily modified here
+    let min_saving = 100;
+    let max_cheat = 20;
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let use_path_index = true;
+    let single_track = grid.is_single_track();
+    let search = if !single_track {
+        CheatSearch::General
+    } else if use_path_index {
+        CheatSearch::PathIndex { threads }
+    } else {
+        CheatSearch::Scan
+    };
 
-    let mut counts: std::collections::HashMap<usize, usize> = std::collections::HashMap::new();
-    for (_, saved_steps) in shortcuts {
-        *counts.entry(saved_steps).or_insert(0) += 1;
-    }
-    let part1_total: usize = counts.values().sum();
+    // Part 1: Single wall shortcuts
+    let report = if single_track {
+        CheatReport::new(grid.find_wall_shortcuts(min_saving))
+    } else {
+        CheatReport::new(grid.find_cheats(search, 2, min_saving))
+    };
     println!(
-        "Part 1 - Single wall shortcuts that save >100 steps: {}",
-        part1_total
+        "Part 1 - Single wall shortcuts that save >={} steps: {}",
+        min_saving,
+        report.total()
     );
 
     // Part 2: Multi-step shortcuts
-    let multi_shortcuts = grid.find_multi_shortcuts(20);
-    let mut multi_counts: std::collections::HashMap<usize, usize> =
-        std::collections::HashMap::new();
-    for (_, saved_steps) in multi_shortcuts {
-        *multi_counts.entry(saved_steps).or_insert(0) += 1;
-    }
-    let part2_total: usize = multi_counts.values().sum();
+    let multi_report = CheatReport::new(grid.find_cheats(search, max_cheat, min_saving));
+    print!("{}", multi_report);
     println!(
-        "Part 2 - Multi-step shortcuts that save >100 steps: {}",
-        part2_total
+        "Part 2 - Multi-step shortcuts of up to {} steps that save >={} steps: {}",
+        max_cheat,
+        min_saving,
+        multi_report.total()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "###############
+#...#...#.....#
+#.#.#.#.#.###.#
+#S#...#.#.#...#
+#######.#.#.###
+#######.#.#...#
+#######.#.###.#
+###..E#...#...#
+###.#######.###
+#...###...#...#
+#.#####.#.###.#
+#.#...#.#.#...#
+#.#.#.#.#.#.###
+#...#...#...###
+###############";
+
+    #[test]
+    fn test_example_wall_cheats() {
+        let grid = Grid::new(EXAMPLE);
+        let expected = BTreeMap::from([
+            (2, 14),
+            (4, 14),
+            (6, 2),
+            (8, 4),
+            (10, 2),
+            (12, 3),
+            (20, 1),
+            (36, 1),
+            (38, 1),
+            (40, 1),
+            (64, 1),
+        ]);
+
+        assert_eq!(
+            CheatReport::new(grid.find_wall_shortcuts(1)).histogram(),
+            expected
+        );
+        assert_eq!(
+            CheatReport::new(grid.find_multi_shortcuts(2, 1)).histogram(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_example_long_cheats() {
+        let grid = Grid::new(EXAMPLE);
+        let report = CheatReport::new(grid.find_multi_shortcuts(20, 50));
+        let expected = BTreeMap::from([
+            (50, 32),
+            (52, 31),
+            (54, 29),
+            (56, 39),
+            (58, 25),
+            (60, 23),
+            (62, 20),
+            (64, 19),
+            (66, 12),
+            (68, 14),
+            (70, 12),
+            (72, 22),
+            (74, 4),
+            (76, 3),
+        ]);
+
+        assert_eq!(report.histogram(), expected);
+        assert!(report
+            .to_string()
+            .starts_with("There are 32 cheats that save 50 picoseconds."));
+    }
+
+    #[test]
+    fn test_path_index_matches_scan() {
+        let grid = Grid::new(EXAMPLE);
+        for max_steps in [2, 6, 20, 30] {
+            for min_saving in [0, 1, 50, 70] {
+                let scan =
+                    CheatReport::new(grid.find_cheats(CheatSearch::Scan, max_steps, min_saving));
+                for threads in [1, 4] {
+                    let search = CheatSearch::PathIndex { threads };
+                    let path = CheatReport::new(grid.find_cheats(search, max_steps, min_saving));
+                    assert_eq!(scan.cheats, path.cheats);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_general_matches_single_track() {
+        let grid = Grid::new(EXAMPLE);
+        assert!(grid.is_single_track());
+
+        for (max_steps, min_saving) in [(2, 1), (6, 0), (20, 50)] {
+            let path =
+                grid.find_cheats(CheatSearch::PathIndex { threads: 1 }, max_steps, min_saving);
+            let general = grid.find_cheats(CheatSearch::General, max_steps, min_saving);
+            assert_eq!(
+                CheatReport::new(path).cheats,
+                CheatReport::new(general).cheats
+            );
+        }
+    }
+
+    #[test]
+    fn test_general_ignores_dead_ends() {
+        // The lower corridor is a dead end behind E, so cheating into it saves nothing
+        let grid = Grid::new(
+            "#########
+#S.....E#
+#######.#
+#.......#
+#########",
+        );
+        assert!(!grid.is_single_track());
+        assert_eq!(grid.path.len(), 7);
+
+        assert!(!grid.find_cheats(CheatSearch::Scan, 2, 1).is_empty());
+        assert!(grid.find_cheats(CheatSearch::General, 2, 1).is_empty());
+    }
+
+    #[test]
+    fn test_general_with_loop() {
+        // Two routes around the block; cheating through it is measured against the shorter one
+        let grid = Grid::new(
+            "#######
+#S....#
+#.###.#
+#.#E#.#
+#.#.#.#
+#.....#
+#######",
+        );
+        assert!(!grid.is_single_track());
+        assert_eq!(grid.path.len(), 9);
+
+        let report = CheatReport::new(grid.find_cheats(CheatSearch::General, 2, 1));
+        assert_eq!(report.histogram(), BTreeMap::from([(2, 1), (4, 2)]));
+    }
+}
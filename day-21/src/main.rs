@@ -1,8 +1,9 @@
+use num_bigint::BigUint;
 use std::collections::HashMap;
-use std::io::{self, Read};
+use std::io;
 
 const NUMERIC: [&str; 4] = ["789", "456", "123", " 0A"];
-const DIRECTIONAL: [&str; 3] = [" ^A", "<v>", " vA"];
+const DIRECTIONAL: [&str; 2] = [" ^A", "<v>"];
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 struct Pos {
@@ -58,15 +59,15 @@ fn paths_between(keypad: &[&str], start: char, end: char) -> Vec<String> {
     let mut paths = Vec::new();
 
     let hor = if dx > 0 {
-        ">".repeat(dx.abs() as usize)
+        ">".repeat(dx.unsigned_abs() as usize)
     } else {
-        "<".repeat(dx.abs() as usize)
+        "<".repeat(dx.unsigned_abs() as usize)
     };
 
     let ver = if dy > 0 {
-        "v".repeat(dy.abs() as usize)
+        "v".repeat(dy.unsigned_abs() as usize)
     } else {
-        "^".repeat(dy.abs() as usize)
+        "^".repeat(dy.unsigned_abs() as usize)
     };
 
     // Try both horizontal then vertical and vertical then horizontal
@@ -81,53 +82,199 @@ fn paths_between(keypad: &[&str], start: char, end: char) -> Vec<String> {
     paths
 }
 
-fn cost_between(
-    cache: &mut HashMap<(char, char, usize), i64>,
+// Press counts grow exponentially with the number of robots, so the cost
+// functions are generic over the integer type. `i64` reports overflow as None,
+// `BigUint` never overflows and is only limited by time.
+trait Presses: Clone + Ord {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+impl Presses for i64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+}
+
+impl Presses for BigUint {
+    fn zero() -> Self {
+        BigUint::from(0u32)
+    }
+
+    fn one() -> Self {
+        BigUint::from(1u32)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+}
+
+// Cheapest presses between two keys, None once they overflow
+type Cache<T> = HashMap<(char, char, usize), Option<T>>;
+
+fn cost_between<T: Presses>(
+    cache: &mut Cache<T>,
     keypad: &[&str],
     start: char,
     end: char,
     links: usize,
-) -> i64 {
+) -> Option<T> {
     if links == 0 {
-        return 1;
+        return Some(T::one());
     }
 
     let key = (start, end, links);
-    if let Some(&cost) = cache.get(&key) {
-        return cost;
+    if let Some(cost) = cache.get(&key) {
+        return cost.clone();
     }
 
+    // Paths that overflow can't be the cheapest one, unless they all do
     let paths = paths_between(keypad, start, end);
     let min_cost = paths
         .iter()
-        .map(|path| cost(cache, &DIRECTIONAL, path, links - 1))
-        .min()
-        .unwrap_or(i64::MAX);
+        .filter_map(|path| cost(cache, &DIRECTIONAL, path, links - 1))
+        .min();
 
-    cache.insert(key, min_cost);
+    // Overflows are cached too, or every deeper link would retry them
+    cache.insert(key, min_cost.clone());
     min_cost
 }
 
-fn cost(
-    cache: &mut HashMap<(char, char, usize), i64>,
-    keypad: &[&str],
-    keys: &str,
-    links: usize,
-) -> i64 {
-    let mut total = 0;
+fn cost<T: Presses>(cache: &mut Cache<T>, keypad: &[&str], keys: &str, links: usize) -> Option<T> {
     let full_keys = format!("A{}", keys);
     let chars: Vec<char> = full_keys.chars().collect();
 
-    for i in 0..chars.len() - 1 {
-        total += cost_between(cache, keypad, chars[i], chars[i + 1], links);
+    let mut total = T::zero();
+    for pair in chars.windows(2) {
+        let step = cost_between(cache, keypad, pair[0], pair[1], links)?;
+        total = total.checked_add(&step)?;
+    }
+    Some(total)
+}
+
+// Rebuilds one concrete optimal key sequence for `keys`. The lengths grow
+// exponentially with `links`, so this is only meant for low robot depths.
+fn sequence(cache: &mut Cache<i64>, keypad: &[&str], keys: &str, links: usize) -> String {
+    let full_keys = format!("A{}", keys);
+    let chars: Vec<char> = full_keys.chars().collect();
+
+    chars
+        .windows(2)
+        .map(|pair| sequence_between(cache, keypad, pair[0], pair[1], links))
+        .collect()
+}
+
+fn sequence_between(
+    cache: &mut Cache<i64>,
+    keypad: &[&str],
+    start: char,
+    end: char,
+    links: usize,
+) -> String {
+    if links == 0 {
+        return end.to_string();
+    }
+
+    // Pick the path the cost model considers cheapest and expand it one layer down
+    let best = paths_between(keypad, start, end)
+        .into_iter()
+        .min_by_key(|path| {
+            cost(cache, &DIRECTIONAL, path, links - 1).expect("Sequence too long to build")
+        })
+        .expect("No path between keys");
+
+    sequence(cache, &DIRECTIONAL, &best, links - 1)
+}
+
+// Replays key presses on a keypad, starting on 'A'. Returns the keys typed, or
+// None if the arm ever leaves the keypad or hovers over the gap.
+fn press(keypad: &[&str], presses: &str) -> Option<String> {
+    let mut current = find_char(keypad, 'A');
+    let mut typed = String::new();
+
+    for key in presses.chars() {
+        if key == 'A' {
+            let row = keypad[current.y as usize];
+            typed.push(row.chars().nth(current.x as usize).unwrap());
+            continue;
+        }
+
+        match walk(keypad, current, &key.to_string()).first() {
+            Some(&c) if c != ' ' => current = find_char(keypad, c),
+            _ => return None,
+        }
+    }
+    Some(typed)
+}
+
+// Sends the top-level sequence through every directional robot and then the
+// numeric keypad, returning what ends up typed on the door.
+fn simulate(presses: &str, robots: usize) -> Option<String> {
+    let mut keys = presses.to_string();
+    for _ in 0..robots {
+        keys = press(&DIRECTIONAL, &keys)?;
     }
-    total
+    press(&NUMERIC, &keys)
 }
 
-fn complexity(code: &str, robots: usize) -> i64 {
+// Returns None if the result does not fit in an i64, see `big_complexity`
+fn complexity(code: &str, robots: usize) -> Option<i64> {
     let mut cache = HashMap::new();
     let numeric_value: i64 = code[..code.len() - 1].parse().unwrap();
-    cost(&mut cache, &NUMERIC, code, robots + 1) * numeric_value
+    cost::<i64>(&mut cache, &NUMERIC, code, robots + 1)?.checked_mul(numeric_value)
+}
+
+fn big_complexity(code: &str, robots: usize) -> BigUint {
+    let mut cache = HashMap::new();
+    let numeric_value: BigUint = code[..code.len() - 1].parse().unwrap();
+    cost::<BigUint>(&mut cache, &NUMERIC, code, robots + 1).unwrap() * numeric_value
+}
+
+// Sums the complexities in i64 and switches to arbitrary precision on overflow
+fn total_complexity(codes: &[&str], robots: usize) -> BigUint {
+    let checked: Option<i64> = codes.iter().try_fold(0i64, |total, &code| {
+        total.checked_add(complexity(code, robots)?)
+    });
+
+    match checked {
+        Some(total) => BigUint::from(total as u64),
+        None => codes.iter().map(|&code| big_complexity(code, robots)).sum(),
+    }
+}
+
+// Ratio of total presses between each robot layer and the one before it,
+// empty when there are no presses to compare
+fn growth_rates(codes: &[&str], max_robots: usize) -> Vec<f64> {
+    let mut cache = HashMap::new();
+    let totals: Vec<BigUint> = (0..=max_robots)
+        .map(|robots| {
+            codes
+                .iter()
+                .map(|&code| cost::<BigUint>(&mut cache, &NUMERIC, code, robots + 1).unwrap())
+                .sum()
+        })
+        .collect();
+
+    // Scale before dividing so the ratio keeps some decimals
+    let scale = 1_000_000u64;
+    totals
+        .windows(2)
+        .take_while(|pair| pair[0] != BigUint::ZERO)
+        .map(|pair| {
+            let ratio = &pair[1] * scale / &pair[0];
+            u64::try_from(&ratio).unwrap() as f64 / scale as f64
+        })
+        .collect()
 }
 
 fn main() -> io::Result<()> {
@@ -135,11 +282,143 @@ fn main() -> io::Result<()> {
 
     let codes: Vec<&str> = input.split_whitespace().collect();
 
-    let part1: i64 = codes.iter().map(|&code| complexity(code, 2)).sum();
-    let part2: i64 = codes.iter().map(|&code| complexity(code, 25)).sum();
+    let part1 = total_complexity(&codes, 2);
+    let part2 = total_complexity(&codes, 25);
 
     println!("{}", part1);
     println!("{}", part2);
 
+    // Deep chains overflow i64 and fall back to arbitrary precision
+    let deep_robots = 100;
+    println!(
+        "{} robots: {}",
+        deep_robots,
+        total_complexity(&codes, deep_robots)
+    );
+
+    for (layer, rate) in growth_rates(&codes, 25).iter().enumerate() {
+        println!(
+            "Growth from {} to {} robots: {:.4}x",
+            layer,
+            layer + 1,
+            rate
+        );
+    }
+
+    // End-to-end check: rebuild the part 1 sequences and replay them
+    let mut cache = HashMap::new();
+    for &code in &codes {
+        let presses = sequence(&mut cache, &NUMERIC, code, 3);
+        let typed = simulate(&presses, 2);
+        let verified = typed.as_deref() == Some(code)
+            && Some(presses.len() as i64) == cost(&mut cache, &NUMERIC, code, 3);
+        println!(
+            "{}: {} ({} presses, {})",
+            code,
+            presses,
+            presses.len(),
+            if verified { "verified" } else { "MISMATCH" }
+        );
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example_sequences() {
+        let codes = ["029A", "980A", "179A", "456A", "379A"];
+        let lengths = [68, 60, 68, 64, 64];
+
+        let mut cache = HashMap::new();
+        for (code, length) in codes.iter().zip(lengths) {
+            let presses = sequence(&mut cache, &NUMERIC, code, 3);
+            assert_eq!(presses.len(), length);
+            assert_eq!(simulate(&presses, 2).as_deref(), Some(*code));
+        }
+
+        assert_eq!(total_complexity(&codes, 2), BigUint::from(126384u32));
+    }
+
+    #[test]
+    fn test_press_rejects_gap() {
+        // Moving left twice from 'A' on the numeric keypad crosses the gap
+        assert_eq!(press(&NUMERIC, "<<A"), None);
+        assert_eq!(press(&DIRECTIONAL, "<<vA"), None);
+        assert_eq!(press(&DIRECTIONAL, "v<<A").as_deref(), Some("<"));
+    }
+
+    #[test]
+    fn test_overflow_falls_back_to_big_integers() {
+        let codes = ["029A", "980A", "179A", "456A", "379A"];
+
+        assert!(complexity("029A", 25).is_some());
+        assert_eq!(complexity("029A", 60), None);
+
+        // Both precisions agree while i64 still fits
+        for robots in [2, 10, 25] {
+            let checked: i64 = codes
+                .iter()
+                .map(|&code| complexity(code, robots).unwrap())
+                .sum();
+            assert_eq!(
+                total_complexity(&codes, robots),
+                BigUint::from(checked as u64)
+            );
+        }
+
+        // Each extra robot multiplies the presses by a roughly constant factor
+        let rates = growth_rates(&codes, 60);
+        let last = rates[rates.len() - 1];
+        assert!(last > 2.0 && last < 3.0);
+        assert!((last - rates[rates.len() - 2]).abs() < 1e-3);
+
+        // No codes means nothing to compare rather than dividing by zero
+        assert!(growth_rates(&[], 25).is_empty());
+    }
+
+    // Presses that overflow past 25, to hit overflow at low robot counts
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Capped(u32);
+
+    impl Presses for Capped {
+        fn zero() -> Self {
+            Capped(0)
+        }
+
+        fn one() -> Self {
+            Capped(1)
+        }
+
+        fn checked_add(&self, other: &Self) -> Option<Self> {
+            let sum = self.0 + other.0;
+            (sum <= 25).then_some(Capped(sum))
+        }
+    }
+
+    #[test]
+    fn test_cost_skips_overflowing_paths() {
+        // No keys to press costs nothing
+        assert_eq!(cost::<i64>(&mut HashMap::new(), &NUMERIC, "", 3), Some(0));
+
+        // From '3' to '7' through two robots costs 23 going left first and
+        // 27 going up first. Only the cheapest path has to fit.
+        let paths = paths_between(&NUMERIC, '3', '7');
+        let capped: Vec<Option<Capped>> = paths
+            .iter()
+            .map(|path| cost(&mut HashMap::new(), &DIRECTIONAL, path, 2))
+            .collect();
+        assert_eq!(capped, vec![Some(Capped(23)), None]);
+        assert_eq!(
+            cost_between(&mut HashMap::new(), &NUMERIC, '3', '7', 3),
+            Some(Capped(23))
+        );
+        assert_eq!(
+            cost_between::<Capped>(&mut HashMap::new(), &NUMERIC, 'A', '7', 3),
+            None
+        );
+    }
+}